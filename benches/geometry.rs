@@ -0,0 +1,88 @@
+use criterion::{black_box, criterion_group, criterion_main, Criterion};
+use macroquad::prelude::*;
+use museum_assassin::{
+    bench::{guard_cone, merged_colliders, shadow_polygon, shadow_segments},
+    ldtk::{load_level_geometry, LevelGeometry},
+    pathfinder::Pathfinder,
+};
+
+fn levels() -> Vec<LevelGeometry> {
+    load_level_geometry(include_str!("../assets/level.ldtk"))
+        .into_iter()
+        .filter(|l| l.level_name.starts_with("Stage_"))
+        .collect()
+}
+
+fn bench_shadow_polygon(c: &mut Criterion) {
+    for level in levels() {
+        let segments = shadow_segments(&level);
+        let center = level.bounds.center();
+        c.bench_function(&format!("shadow_polygon/{}", level.level_name), |b| {
+            b.iter(|| shadow_polygon(black_box(center), &segments, 1024.0))
+        });
+    }
+}
+
+fn bench_guard_cone(c: &mut Criterion) {
+    for level in levels() {
+        let segments = shadow_segments(&level);
+        let nodes = level
+            .patrol_paths
+            .iter()
+            .flatten()
+            .copied()
+            .collect::<Vec<_>>();
+        if nodes.is_empty() {
+            continue;
+        }
+        c.bench_function(&format!("guard_cone/{}", level.level_name), |b| {
+            b.iter(|| {
+                for &(pos, facing) in nodes.iter() {
+                    black_box(guard_cone(pos, facing, &segments));
+                }
+            })
+        });
+    }
+}
+
+fn bench_get_path(c: &mut Criterion) {
+    for level in levels() {
+        let colliders = merged_colliders(&level);
+        // every leg a guard walks, with roughly a guard's footprint
+        let legs = level
+            .patrol_paths
+            .iter()
+            .flat_map(|path| path.windows(2).map(|w| (w[0].0, w[1].0)))
+            .collect::<Vec<_>>();
+        if legs.is_empty() {
+            continue;
+        }
+        let size = vec2(16.0, 16.0);
+        for (allow_diagonals, suffix) in [(false, ""), (true, "/diagonal")] {
+            let pathfinder = Pathfinder::new(
+                level.bounds.w,
+                level.bounds.h,
+                level.grid,
+                &colliders,
+                allow_diagonals,
+            );
+            c.bench_function(&format!("get_path/{}{suffix}", level.level_name), |b| {
+                b.iter(|| {
+                    for &(from, to) in legs.iter() {
+                        let rect =
+                            Rect::new(from.x - size.x / 2.0, from.y - size.y / 2.0, size.x, size.y);
+                        black_box(pathfinder.get_path(rect, to));
+                    }
+                })
+            });
+        }
+    }
+}
+
+criterion_group!(
+    benches,
+    bench_shadow_polygon,
+    bench_guard_cone,
+    bench_get_path
+);
+criterion_main!(benches);
@@ -48,6 +48,14 @@ lazy_static! {
         .into()
     );
     static ref LOADED_CACHE: Mutex<HashMap<PathBuf, Texture2D>> = Mutex::new(HashMap::new());
+    // texturepacker atlas json, keyed by path like the textures, for targets without a filesystem.
+    // every atlas handed to SpriteSheet::from_json needs an include_str! entry here to load on
+    // web, none of the sprites are packed into one yet
+    static ref ATLAS_JSON: Mutex<HashMap<PathBuf, &'static str>> = Mutex::new([].into());
+}
+
+pub fn embedded_atlas_json(path: impl AsRef<Path>) -> Option<&'static str> {
+    ATLAS_JSON.lock().unwrap().get(path.as_ref()).copied()
 }
 
 pub async fn load_fast_texture(path: impl AsRef<Path>) -> Texture2D {
@@ -55,3 +55,16 @@ pub fn draw_dotted_line(
         cursor += d + spacer;
     }
 }
+
+// a soft ellipse under a character's feet, smaller and fainter the higher `elevation` is
+pub fn draw_ground_shadow(rect: Rect, elevation: f32) {
+    let scale = 1.0 / (1.0 + elevation.max(0.0));
+    draw_ellipse(
+        rect.center().x,
+        rect.bottom(),
+        rect.w * 0.5 * scale,
+        3.0 * scale,
+        0.0,
+        Color::new(0.0, 0.0, 0.0, 0.3 * scale),
+    );
+}
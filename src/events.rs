@@ -0,0 +1,28 @@
+use std::sync::Mutex;
+
+use lazy_static::lazy_static;
+
+use crate::EnemyId;
+
+lazy_static! {
+    static ref EVENTS: Mutex<Vec<GameEvent>> = Mutex::new(Vec::new());
+}
+
+// things that happened during a frame, for systems like stats that only need to react to them
+#[derive(Debug, Clone, PartialEq)]
+pub enum GameEvent {
+    EnemyKilled(EnemyId),
+    PlayerDetected,
+    LevelCleared(String),
+    ArrowFired,
+    BodyPickedUp(EnemyId),
+}
+
+pub fn emit(event: GameEvent) {
+    EVENTS.lock().unwrap().push(event);
+}
+
+/// Takes every event emitted since the last call, `run` does this once a frame.
+pub fn drain_events() -> Vec<GameEvent> {
+    std::mem::take(&mut *EVENTS.lock().unwrap())
+}
@@ -43,12 +43,62 @@ pub fn cast_rect(mut rect: Rect, to: Vec2, barriers: &[Rect]) -> bool {
     (to - rect.center()).length() <= accept_interval
 }
 
+// colliders are shrunk by this much before overlap tests, so just touching one doesn't block
+// movement along it
+pub const COLLIDER_EPSILON: f32 = 0.1;
+
+/// How much of the move `v` a `rect` can make among `colliders`, sliding along whatever it hits.
+pub fn slide_rect(rect: Rect, mut v: Vec2, colliders: &[Rect]) -> Vec2 {
+    let blocked = |d: Vec2| {
+        let moved = Rect {
+            x: rect.x + d.x,
+            y: rect.y + d.y,
+            ..rect
+        };
+        colliders.iter().any(|c| {
+            moved.overlaps(&Rect {
+                x: c.x + COLLIDER_EPSILON,
+                y: c.y + COLLIDER_EPSILON,
+                w: c.w - 2.0 * COLLIDER_EPSILON,
+                h: c.h - 2.0 * COLLIDER_EPSILON,
+            })
+        })
+    };
+    if blocked(vec2(v.x, 0.0)) {
+        v.x = 0.0;
+    }
+    if blocked(vec2(0.0, v.y)) {
+        v.y = 0.0;
+    }
+    // each axis can be free on its own and still clip a corner where two rects meet, which
+    // would leave the rect stuck inside it next frame
+    if v.x != 0.0 && v.y != 0.0 && blocked(v) {
+        if v.x.abs() > v.y.abs() {
+            v.y = 0.0;
+        } else {
+            v.x = 0.0;
+        }
+    }
+    v
+}
+
 pub fn rotate_vec2(v: Vec2, angle: f32) -> Vec2 {
     let c = angle.cos();
     let s = angle.sin();
     vec2(v.x * c - v.y * s, v.x * s + v.y * c)
 }
 
+// the distance from `p` to the closest point on the segment from `a` to `b`
+pub fn segment_distance(p: Vec2, a: Vec2, b: Vec2) -> f32 {
+    let ab = b - a;
+    let t = if ab == Vec2::ZERO {
+        0.0
+    } else {
+        ((p - a).dot(ab) / ab.length_squared()).clamp(0.0, 1.0)
+    };
+    (a + ab * t).distance(p)
+}
+
 pub fn triangle_contains(p: Vec2, a: Vec2, b: Vec2, c: Vec2) -> bool {
     let area = (b - a).perp_dot(c - a) * 0.5;
     let bary_a = (p - b).perp_dot(p - c) / area;
@@ -57,11 +107,8 @@ pub fn triangle_contains(p: Vec2, a: Vec2, b: Vec2, c: Vec2) -> bool {
     bary_a > 0.0 && bary_b > 0.0 && bary_c > 0.0
 }
 
+// only ever combines two rects that share a full edge, so the covered area never changes
 pub fn shape_preserving_rect_merge(mut rects: Vec<Rect>) -> Vec<Rect> {
-    if rects.is_empty() {
-        return rects;
-    }
-
     // sort by y value then by x
     rects.sort_by(|a, b| {
         if a.y == b.y {
@@ -71,19 +118,15 @@ pub fn shape_preserving_rect_merge(mut rects: Vec<Rect>) -> Vec<Rect> {
         }
     });
 
-    let mut x_merged = Vec::new();
-    let mut cur = rects.pop().unwrap();
-
-    while let Some(back) = rects.last() {
-        if back.y == cur.y && back.h == cur.h && back.x + back.w == cur.x {
-            cur = cur.combine_with(*back);
-            rects.pop();
-        } else {
-            x_merged.push(cur);
-            cur = rects.pop().unwrap();
+    let mut x_merged: Vec<Rect> = Vec::new();
+    while let Some(next) = rects.pop() {
+        match x_merged.last_mut() {
+            Some(cur) if next.y == cur.y && next.h == cur.h && next.x + next.w == cur.x => {
+                *cur = cur.combine_with(next);
+            }
+            _ => x_merged.push(next),
         }
     }
-    x_merged.push(cur);
 
     x_merged.sort_by(|a, b| {
         if a.x == b.x {
@@ -93,19 +136,15 @@ pub fn shape_preserving_rect_merge(mut rects: Vec<Rect>) -> Vec<Rect> {
         }
     });
 
-    let mut y_merged = Vec::new();
-    let mut cur = x_merged.pop().unwrap();
-
-    while let Some(back) = x_merged.last() {
-        if back.x == cur.x && back.w == cur.w && back.y + back.h == cur.y {
-            cur = cur.combine_with(*back);
-            x_merged.pop();
-        } else {
-            y_merged.push(cur);
-            cur = x_merged.pop().unwrap();
+    let mut y_merged: Vec<Rect> = Vec::new();
+    while let Some(next) = x_merged.pop() {
+        match y_merged.last_mut() {
+            Some(cur) if next.x == cur.x && next.w == cur.w && next.y + next.h == cur.y => {
+                *cur = cur.combine_with(next);
+            }
+            _ => y_merged.push(next),
         }
     }
-    y_merged.push(cur);
 
     y_merged
 }
@@ -118,19 +157,19 @@ pub fn line_rect_intersect(start: Vec2, end: Vec2, rect: Rect) -> Option<(Vec2,
 
     let ints = [
         (
-            intersect_lines(r1, r2, start, end),
+            intersect_lines(start, end, r1, r2),
             (r1 - r2).perp().normalize(),
         ),
         (
-            intersect_lines(r1, r3, start, end),
+            intersect_lines(start, end, r1, r3),
             (r1 - r3).perp().normalize(),
         ),
         (
-            intersect_lines(r3, r4, start, end),
+            intersect_lines(start, end, r3, r4),
             (r3 - r4).perp().normalize(),
         ),
         (
-            intersect_lines(r2, r4, start, end),
+            intersect_lines(start, end, r2, r4),
             (r2 - r4).perp().normalize(),
         ),
     ];
@@ -140,18 +179,42 @@ pub fn line_rect_intersect(start: Vec2, end: Vec2, rect: Rect) -> Option<(Vec2,
             .partial_cmp(&(b.0 - start).length())
             .unwrap()
     };
-    let min = ints
+    let hits = ints
         .iter()
         .filter_map(|(i, n)| i.map(|i| (i, *n)))
-        .min_by(cmp);
-    let max = ints
+        .collect::<Vec<_>>();
+    let (min_i, _) = hits.iter().copied().min_by(cmp)?;
+    let (max_i, max_n) = hits.iter().copied().max_by(cmp)?;
+
+    // a hit right on a corner touches two edges, so don't let edge order pick the normal
+    let min_normals = hits
         .iter()
-        .filter_map(|(i, n)| i.map(|i| (i, *n)))
-        .max_by(cmp);
-    match (min, max) {
-        (Some((min_i, min_n)), Some((max_i, max_n))) => Some((min_i, min_n, max_i, max_n)),
-        _ => None,
+        .filter(|(i, _)| (*i - min_i).length() < CORNER_EPSILON)
+        .map(|(_, n)| *n)
+        .collect::<Vec<_>>();
+    let min_n = corner_normal(end - start, &min_normals);
+    Some((min_i, min_n, max_i, max_n))
+}
+
+pub const CORNER_EPSILON: f32 = 0.001;
+
+/// Picks the normal of the face `dir` hits most head-on out of `normals`, all taken at the same point.
+/// If it hits several faces equally, like going straight into a corner, their average is used instead.
+pub fn corner_normal(dir: Vec2, normals: &[Vec2]) -> Vec2 {
+    if normals.len() == 1 {
+        return normals[0];
     }
+    let dir = dir.normalize_or_zero();
+    let facing = normals
+        .iter()
+        .map(|&n| if n.dot(dir) > 0.0 { -n } else { n })
+        .collect::<Vec<_>>();
+    let best = facing.iter().map(|n| -n.dot(dir)).fold(f32::MIN, f32::max);
+    facing
+        .iter()
+        .filter(|n| (-n.dot(dir) - best).abs() < CORNER_EPSILON)
+        .fold(Vec2::ZERO, |sum, n| sum + *n)
+        .normalize_or(-dir)
 }
 
 /// Get any intersection point between line segments.
@@ -206,14 +269,18 @@ pub fn intersect_lines(a_start: Vec2, a_end: Vec2, b_start: Vec2, b_end: Vec2) -
 
         // If r × s = 0 and (q − p) × r = 0, then the two lines are collinear.
         if ulps_eq!(q_minus_p_cross_r, 0.0) {
-            // let r_dot_r = r.dot(r);
-            // let r_div_r_dot_r = r / r_dot_r;
-            // let s_dot_r = s.dot(r);
-            // let t0 = q_minus_p.dot(r_div_r_dot_r);
-            // let t1 = t0 + s_dot_r / r_dot_r;
-
-            // TODO: differentiate overlaps instead of just returning point
-            Some(a_start)
+            let r_dot_r = r.dot(r);
+            let t0 = q_minus_p.dot(r) / r_dot_r;
+            let t1 = t0 + s.dot(r) / r_dot_r;
+
+            // return the point of the overlap closest to a_start
+            let lo = t0.min(t1).max(0.0);
+            let hi = t0.max(t1).min(1.0);
+            if lo <= hi {
+                Some(p + r * lo)
+            } else {
+                None
+            }
         } else {
             // If r × s = 0 and (q − p) × r ≠ 0,
             // then the two lines are parallel and non-intersecting.
@@ -267,3 +334,88 @@ pub fn intersect_line_point(line_start: Vec2, line_end: Vec2, point: Vec2) -> Op
 pub fn ulps_eq_vecs(v1: Vec2, v2: Vec2) -> bool {
     ulps_eq!(v1.x, v2.x) && ulps_eq!(v1.y, v2.y)
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const TILE: f32 = 16.0;
+
+    fn tiles(cells: &[(i32, i32)]) -> Vec<Rect> {
+        cells
+            .iter()
+            .map(|&(x, y)| Rect::new(x as f32 * TILE, y as f32 * TILE, TILE, TILE))
+            .collect()
+    }
+
+    // checks the merge covers the same points as the input, sampled every half tile, without
+    // any of the merged rects overlapping
+    fn assert_same_union(input: Vec<Rect>) {
+        let merged = shape_preserving_rect_merge(input.clone());
+        for x in -2..40 {
+            for y in -2..40 {
+                let p = vec2(x as f32, y as f32) * TILE / 2.0 + TILE / 4.0;
+                let covered = |rects: &[Rect]| rects.iter().filter(|r| r.contains(p)).count();
+                assert_eq!(covered(&merged), covered(&input).min(1), "at {p}");
+            }
+        }
+        let area = |rects: &[Rect]| rects.iter().map(|r| r.w * r.h).sum::<f32>();
+        assert_eq!(area(&merged), area(&input));
+    }
+
+    #[test]
+    fn rect_merge_joins_a_single_row() {
+        let row = tiles(&[(0, 0), (1, 0), (2, 0), (3, 0)]);
+        assert_eq!(shape_preserving_rect_merge(row.clone()).len(), 1);
+        assert_same_union(row);
+    }
+
+    #[test]
+    fn rect_merge_keeps_an_l_shape() {
+        let l = tiles(&[(0, 0), (0, 1), (0, 2), (1, 2), (2, 2)]);
+        assert_eq!(shape_preserving_rect_merge(l.clone()).len(), 2);
+        assert_same_union(l);
+    }
+
+    #[test]
+    fn rect_merge_leaves_a_checkerboard_alone() {
+        let cells = (0..6)
+            .flat_map(|x| (0..6).map(move |y| (x, y)))
+            .filter(|(x, y)| (x + y) % 2 == 0)
+            .collect::<Vec<_>>();
+        let board = tiles(&cells);
+        assert_eq!(
+            shape_preserving_rect_merge(board.clone()).len(),
+            board.len()
+        );
+        assert_same_union(board);
+    }
+
+    // walks `rect`, pressed up against a five tile `wall`, along all of it one `v` at a time,
+    // checking the move along the wall is never cut short at the seams between its tiles
+    fn assert_slides_along(wall: &[Rect], mut rect: Rect, v: Vec2, along: Vec2) {
+        for _ in 0..(5.0 * TILE / 2.0) as usize {
+            let moved = slide_rect(rect, v, wall);
+            assert_eq!(moved, along, "at {:?}", rect.point());
+            rect = rect.offset(moved);
+        }
+    }
+
+    #[test]
+    fn slides_along_a_floor_of_five_tiles() {
+        let wall = tiles(&[(0, 0), (1, 0), (2, 0), (3, 0), (4, 0)]);
+        let rect = Rect::new(-8.0, -32.0, 16.0, 32.0);
+        assert_slides_along(&wall, rect, vec2(2.0, 2.0), vec2(2.0, 0.0));
+        let rect = Rect::new(5.0 * TILE - 8.0, -32.0, 16.0, 32.0);
+        assert_slides_along(&wall, rect, vec2(-2.0, 2.0), vec2(-2.0, 0.0));
+    }
+
+    #[test]
+    fn slides_along_a_side_wall_of_five_tiles() {
+        let wall = tiles(&[(0, 0), (0, 1), (0, 2), (0, 3), (0, 4)]);
+        let rect = Rect::new(TILE, -16.0, 16.0, 32.0);
+        assert_slides_along(&wall, rect, vec2(-2.0, 2.0), vec2(0.0, 2.0));
+        let rect = Rect::new(-16.0, 5.0 * TILE - 16.0, 16.0, 32.0);
+        assert_slides_along(&wall, rect, vec2(2.0, -2.0), vec2(0.0, -2.0));
+    }
+}
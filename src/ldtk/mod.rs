@@ -1,12 +1,16 @@
+use std::f32::consts::PI;
+
 use itertools::izip;
 use macroquad::prelude::*;
-use schema::{EntityInstance, GridPoint, Ldtk};
+use schema::{EntityInstance, GridPoint, LayerInstance, Ldtk};
 use serde::de::DeserializeOwned;
 
 use crate::{
     geometry_utils::GeoUtilsFloatExts,
+    pathfinder::Grid,
+    save::Medal,
     sprite::{Sprite, SpriteSheet},
-    Draw, Light, PatrolNode, PatrolPath, Trigger, TriggerType,
+    Draw, Light, PatrolNode, PatrolPath, Time, Trigger, TriggerType,
 };
 
 mod schema;
@@ -14,6 +18,7 @@ mod schema;
 pub struct TileSprite {
     pub sprite: Sprite,
     pub pos: Vec2,
+    pub opacity: f32,
 }
 
 impl TileSprite {
@@ -28,7 +33,13 @@ impl TileSprite {
 
 impl Draw for TileSprite {
     fn draw(&self) {
-        self.sprite.draw(self.pos);
+        self.sprite.draw_colored(
+            self.pos,
+            Color {
+                a: self.opacity,
+                ..WHITE
+            },
+        );
     }
 
     fn sort_order(&self) -> f32 {
@@ -36,15 +47,66 @@ impl Draw for TileSprite {
     }
 }
 
+// an action a tutorial popup waits for before it goes away
+#[derive(Clone, Copy, Debug)]
+pub enum PopUpGate {
+    Move,
+    Aim,
+    Shoot,
+    PickUp,
+    SwitchArrow,
+}
+
+impl PopUpGate {
+    // a typo in a level shouldn't stop it from loading, the popup just won't wait for anything
+    fn from_str(s: &str) -> Option<Self> {
+        match s {
+            "Move" => Some(Self::Move),
+            "Aim" => Some(Self::Aim),
+            "Shoot" => Some(Self::Shoot),
+            "PickUp" => Some(Self::PickUp),
+            "SwitchArrow" => Some(Self::SwitchArrow),
+            _ => {
+                eprintln!("warning: unknown popup gate {s}, the popup won't wait for anything");
+                None
+            }
+        }
+    }
+}
+
+// what has to be done before a level's exits open up
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum WinCondition {
+    #[default]
+    KillAll,
+    // the level is cleared by reaching an exit, no kills needed
+    ReachExit,
+    // only guards on patrol paths marked as targets have to be killed
+    KillTargets,
+}
+
+impl WinCondition {
+    fn from_str(s: &str) -> Self {
+        match s {
+            "KillAll" => Self::KillAll,
+            "ReachExit" => Self::ReachExit,
+            "KillTargets" => Self::KillTargets,
+            _ => panic!("unknown win condition {s}"),
+        }
+    }
+}
+
 #[derive(Clone)]
 pub struct PopUp {
     pub rect: Rect,
     pub text: String,
     pub triggered: bool,
+    // gated popups stay on screen without pausing until the player does the action
+    pub gate: Option<PopUpGate>,
 }
 
 impl PopUp {
-    pub fn new(pos: Vec2, size: Vec2, text: String) -> Self {
+    pub fn new(pos: Vec2, size: Vec2, text: String, gate: Option<PopUpGate>) -> Self {
         Self {
             rect: Rect {
                 x: pos.x,
@@ -54,14 +116,45 @@ impl PopUp {
             },
             text,
             triggered: false,
+            gate,
         }
     }
 }
 
+// a wall guards can only see through from one side, like a one-way mirror
+#[derive(Clone, Copy)]
+pub struct OneWayWindow {
+    pub rect: Rect,
+    // the side it can be seen through from, as a unit vector along one axis
+    pub see_from: Vec2,
+}
+
+impl OneWayWindow {
+    pub fn is_see_through_from(&self, pos: Vec2) -> bool {
+        let half_extent = (self.rect.size() / 2.0).dot(self.see_from.abs());
+        (pos - self.rect.center()).dot(self.see_from) > half_extent
+    }
+}
+
+fn side_from_str(s: &str) -> Vec2 {
+    match s {
+        "Up" => vec2(0.0, -1.0),
+        "Down" => vec2(0.0, 1.0),
+        "Left" => vec2(-1.0, 0.0),
+        "Right" => vec2(1.0, 0.0),
+        _ => panic!("unknown side {s}"),
+    }
+}
+
 #[derive(Default)]
 pub struct Level {
     pub player_spawn: Vec2,
+    // the way the player faces on spawn as a unit vector along one axis, or zero for the default
+    pub player_facing: Vec2,
     pub bg_color: Color,
+    pub ambient_color: Color,
+    // how bright the parts of the level nobody can see are, from 0 (black) to 1 (undimmed)
+    pub dim_factor: f32,
     pub colliders: Vec<Rect>,
     pub lights: Vec<Light>,
     pub shadow_casters: Vec<Rect>,
@@ -71,12 +164,76 @@ pub struct Level {
     pub triggers: Vec<Trigger>,
     pub patrol_paths: Vec<PatrolPath>,
     pub center: Vec2,
-    pub transitions: Vec<(Vec2, String)>,
+    // each exit's rect and the level it leads to
+    pub transitions: Vec<(Rect, String)>,
     pub bounds: Rect,
     pub level_name: String,
     pub camera_height: f32,
     pub camera_tracking: f32,
     pub popups: Vec<PopUp>,
+    // the slowest clear time that still earns each medal, best medal first
+    pub medal_times: Vec<(Medal, Time)>,
+    pub win_condition: WinCondition,
+    // the grid of the collision layer, which the pathfinder lines its cells up with
+    pub grid: Grid,
+    pub one_way_windows: Vec<OneWayWindow>,
+}
+
+impl Level {
+    /// Flips the whole level left to right, for mirrored practice runs.
+    pub fn mirror(&mut self) {
+        let (left, right) = (self.bounds.left(), self.bounds.right());
+        let mirror_x = |x: f32| left + right - x;
+        let mirror_pos = |p: Vec2| vec2(mirror_x(p.x), p.y);
+        let mirror_rect = |r: Rect| Rect {
+            x: mirror_x(r.right()),
+            ..r
+        };
+
+        self.player_spawn = mirror_pos(self.player_spawn);
+        self.player_facing.x = -self.player_facing.x;
+        self.center = mirror_pos(self.center);
+        for r in self
+            .colliders
+            .iter_mut()
+            .chain(self.shadow_casters.iter_mut())
+        {
+            *r = mirror_rect(*r);
+        }
+        self.grid.offset.x = (left + right - self.grid.offset.x).rem_euclid(self.grid.cell_size.x);
+        for t in self.triggers.iter_mut() {
+            t.rect = mirror_rect(t.rect);
+        }
+        for p in self.popups.iter_mut() {
+            p.rect = mirror_rect(p.rect);
+        }
+        for w in self.one_way_windows.iter_mut() {
+            w.rect = mirror_rect(w.rect);
+            w.see_from.x = -w.see_from.x;
+        }
+        for l in self.lights.iter_mut() {
+            l.pos = mirror_pos(l.pos);
+        }
+        for (rect, _) in self.transitions.iter_mut() {
+            *rect = mirror_rect(*rect);
+        }
+        for path in self.patrol_paths.iter_mut() {
+            for node in path.nodes.iter_mut() {
+                node.pos = mirror_pos(node.pos);
+                // reflecting across a vertical line turns an angle a into pi - a
+                node.facing = (PI - node.facing).normalized_rads();
+            }
+        }
+        for t in self
+            .structure_sprites
+            .iter_mut()
+            .chain(self.auto_sprites.iter_mut())
+            .chain(self.decoration_sprites.iter_mut())
+        {
+            t.pos.x = mirror_x(t.pos.x + t.sprite.size().x);
+            t.sprite.toggle_flip_x();
+        }
+    }
 }
 
 pub async fn get_level_indices(ldtk_string: &str) -> Vec<usize> {
@@ -91,7 +248,23 @@ pub async fn load_ldtk(ldtk_str: &str, idx: usize) -> Level {
 
     let level = &ldtk.levels[idx];
     ret.bg_color = hex_str_to_color(&level.bg_color);
+    ret.ambient_color = get_level_field::<String>(level, "AmbientColor")
+        .map(|c| hex_str_to_color(&c))
+        .unwrap_or(WHITE);
+    ret.dim_factor = get_level_field::<f32>(level, "DimFactor").unwrap_or(0.8);
     ret.level_name = level.identifier.clone();
+    ret.win_condition = get_level_field::<String>(level, "WinCondition")
+        .map(|s| WinCondition::from_str(&s))
+        .unwrap_or_default();
+    // levels without par times just don't award medals
+    ret.medal_times = [
+        (Medal::Gold, "GoldTime"),
+        (Medal::Silver, "SilverTime"),
+        (Medal::Bronze, "BronzeTime"),
+    ]
+    .into_iter()
+    .filter_map(|(medal, field)| get_level_field::<f32>(level, field).map(|t| (medal, t as Time)))
+    .collect();
 
     ret.center = vec2(level.px_wid as f32, level.px_hei as f32) / 2.0;
     ret.camera_height = (level.px_hei as f32 * 0.9).max(512.0);
@@ -113,53 +286,51 @@ pub async fn load_ldtk(ldtk_str: &str, idx: usize) -> Level {
             layer.px_total_offset_y as f32,
         );
 
+        if layer.identifier == "Collisions" {
+            ret.grid = Grid {
+                cell_size: vec2(grid_size, grid_size),
+                offset: layer_offset,
+            };
+        }
+
         let spritesheet = if let Some(rel_path) = layer.tileset_rel_path.as_ref() {
             Some(SpriteSheet::from_texture_path(format!("assets/{}", rel_path)).await)
         } else {
             None
         };
 
-        if layer.int_grid_csv.len() > 0 {
-            let width_gr = layer.c_wid;
-            let height_gr = layer.c_hei;
-
-            for y in 0..height_gr {
-                for x in 0..width_gr {
-                    let pos = vec2(grid_size * x as f32, grid_size * y as f32) + layer_offset;
-                    let is_wall = layer.int_grid_csv[(x + y * width_gr) as usize] == 1
-                        && layer.identifier == "Collisions";
-                    let is_sc = layer.int_grid_csv[(x + y * width_gr) as usize] == 1
-                        && (layer.identifier == "ShadowCasters"
-                            || layer.identifier == "StructureGrid");
-                    let is_level_transition_trigger = layer.int_grid_csv[(x + y * width_gr) as usize] == 1
-                        && layer.identifier == "Triggers";
-                    let is_won_game_trigger = layer.int_grid_csv[(x + y * width_gr) as usize] == 2
-                        && layer.identifier == "Triggers";
-                    if is_wall {
-                        ret.colliders
-                            .push(Rect::new(pos.x, pos.y, grid_size, grid_size));
-                    }
-                    if is_sc {
-                        ret.shadow_casters
-                            .push(Rect::new(pos.x, pos.y, grid_size, grid_size));
-                    }
-                    if is_level_transition_trigger {
-                        ret.triggers.push(Trigger {
-                            rect: Rect::new(pos.x, pos.y, grid_size, grid_size),
-                            ty: TriggerType::LevelTransition,
-                        });
-                    }
-                    if is_won_game_trigger {
-                        ret.triggers.push(Trigger {
-                            rect: Rect::new(pos.x, pos.y, grid_size, grid_size),
-                            ty: TriggerType::WonGame,
-                        });
-                    }
-                }
+        for (cell, value) in int_grid_cells(layer) {
+            let is_level_transition_trigger = value == 1 && layer.identifier == "Triggers";
+            let is_won_game_trigger = value == 2 && layer.identifier == "Triggers";
+            if is_wall(layer, value) {
+                ret.colliders.push(cell);
+            }
+            if is_shadow_caster(layer, value) {
+                ret.shadow_casters.push(cell);
+            }
+            if is_level_transition_trigger {
+                ret.triggers.push(Trigger {
+                    rect: cell,
+                    ty: TriggerType::LevelTransition,
+                    destination: None,
+                });
+            }
+            if is_won_game_trigger {
+                ret.triggers.push(Trigger {
+                    rect: cell,
+                    ty: TriggerType::WonGame,
+                    destination: None,
+                });
             }
         }
 
-        for tile in layer.auto_layer_tiles.iter().chain(layer.grid_tiles.iter()) {
+        // hidden layers are only there as a reference for whoever is editing the level
+        for tile in layer
+            .auto_layer_tiles
+            .iter()
+            .chain(layer.grid_tiles.iter())
+            .filter(|_| layer.visible)
+        {
             let pos = vec2(tile.px[0] as f32, tile.px[1] as f32) + layer_offset;
             let src = Rect {
                 x: tile.src[0] as f32,
@@ -181,6 +352,7 @@ pub async fn load_ldtk(ldtk_str: &str, idx: usize) -> Level {
                     .flip_x(tile.f & 0x1 != 0)
                     .flip_y(tile.f & 0x2 != 0),
                 pos,
+                opacity: (layer.opacity * tile.a) as f32,
             });
         }
 
@@ -196,11 +368,23 @@ pub async fn load_ldtk(ldtk_str: &str, idx: usize) -> Level {
             match entity.identifier.as_str() {
                 "PopUp" => {
                     let text = get_entity_field::<String>(entity, "Text");
-                    ret.popups.push(PopUp::new(pos - size / 2.0, size, text));
+                    let gate = get_optional_entity_field::<String>(entity, "WaitFor")
+                        .and_then(|s| PopUpGate::from_str(&s));
+                    ret.popups
+                        .push(PopUp::new(pos - size / 2.0, size, text, gate));
+                }
+                "LevelTransition" => ret.transitions.push((
+                    Rect::new(pos.x - size.x / 2.0, pos.y - size.y / 2.0, size.x, size.y),
+                    get_entity_field::<String>(entity, "Level"),
+                )),
+                // windows block movement like walls, but only block sight from one side
+                "OneWayWindow" => {
+                    let rect =
+                        Rect::new(pos.x - size.x / 2.0, pos.y - size.y / 2.0, size.x, size.y);
+                    let see_from = side_from_str(&get_entity_field::<String>(entity, "SeeFrom"));
+                    ret.colliders.push(rect);
+                    ret.one_way_windows.push(OneWayWindow { rect, see_from });
                 }
-                "LevelTransition" => ret
-                    .transitions
-                    .push((pos, get_entity_field::<String>(entity, "Level"))),
                 "Light" => {
                     let color = hex_str_to_color(&get_entity_field::<String>(entity, "Color"));
                     ret.lights.push(Light {
@@ -218,97 +402,288 @@ pub async fn load_ldtk(ldtk_str: &str, idx: usize) -> Level {
                         ret.camera_tracking = t;
                     }
                 }
-                "PatrolPath" => {
-                    let mut locs = vec![pos];
-                    locs.extend(
-                        get_entity_field::<Vec<GridPoint>>(entity, "Path")
-                            .iter()
-                            .map(grid_point_to_vec2),
-                    );
-                    let full_circle = get_entity_field::<bool>(entity, "FullCircle");
-                    let facings = get_entity_field::<Vec<GridPoint>>(entity, "Facing")
-                        .iter()
-                        .enumerate()
-                        .map(|(i, p)| {
-                            let p = grid_point_to_vec2(p);
-                            (p - locs[i])
-                                .normalize()
-                                .angle_between(vec2(1.0, 0.0))
-                                .normalized_rads()
-                        })
-                        .collect::<Vec<_>>();
-                    let wait_timings = get_entity_field::<Vec<f32>>(entity, "WaitTiming")
-                        .iter()
-                        .map(|&t| t as f64)
-                        .collect::<Vec<_>>();
-                    let walk_timings = get_entity_field::<Vec<f32>>(entity, "WalkTiming")
-                        .iter()
-                        .map(|&t| t as f64)
-                        .collect::<Vec<_>>();
-                    let mut walk_timings = walk_timings
-                        .into_iter()
-                        .map(|t| Some(t))
-                        .collect::<Vec<_>>();
-                    if !full_circle {
-                        walk_timings.push(None);
-                    }
-                    let start = get_entity_field::<i32>(entity, "Start");
-                    let extra_spawns = get_entity_field::<Vec<i32>>(entity, "ExtraSpawnAt");
-                    assert!(
-                        !extra_spawns.contains(&start),
-                        "{}: extra_spawns contains start",
-                        level.identifier
-                    );
-                    assert!(
-                        locs.len() == facings.len()
-                            && locs.len() == wait_timings.len()
-                            && locs.len() == walk_timings.len(),
-                        "{}, locs={}, facings={}, wait={}, walk={}",
-                        level.identifier,
-                        locs.len(),
-                        facings.len(),
-                        wait_timings.len(),
-                        walk_timings.len()
-                    );
-                    let patrol_nodes = izip!(locs, facings, wait_timings, walk_timings)
-                        .map(|(pos, facing, wait, walk)| PatrolNode {
-                            pos,
-                            facing,
-                            wait,
-                            walk,
-                        })
-                        .collect::<Vec<_>>();
-                    for &curr in [start].iter().chain(extra_spawns.iter()) {
-                        ret.patrol_paths.push(PatrolPath {
-                            nodes: patrol_nodes.clone(),
-                            curr: curr as isize,
-                            timer: None,
-                            forwards: true,
-                            full_circle,
-                        });
-                    }
-                }
+                "PatrolPath" => ret.patrol_paths.extend(patrol_paths(
+                    entity,
+                    pos,
+                    &grid_point_to_vec2,
+                    &level.identifier,
+                )),
                 "PlayerSpawn" => {
                     ret.player_spawn = pos;
+                    // the sprites only face along the axes, so snap to whichever is closest
+                    if let Some(p) = get_optional_entity_field::<GridPoint>(entity, "Facing") {
+                        let d = grid_point_to_vec2(&p) - pos;
+                        ret.player_facing = if d.x.abs() >= d.y.abs() {
+                            vec2(d.x.signum(), 0.0)
+                        } else {
+                            vec2(0.0, d.y.signum())
+                        };
+                    }
                 }
                 _ => {}
             }
         }
     }
 
+    // every transition cell is given its exit before merging, so exits that are close together
+    // don't get merged into one
+    lead_to_exits(&mut ret.triggers, &ret.transitions);
     ret.triggers = merge_triggers(ret.triggers);
     ret.decoration_sprites = merge_tile_layer(&mut ret.decoration_sprites);
     ret
 }
 
+// one path for the patrol's start and another for each of its extra spawns
+fn patrol_paths(
+    entity: &EntityInstance,
+    pos: Vec2,
+    grid_point_to_vec2: &impl Fn(&GridPoint) -> Vec2,
+    level_name: &str,
+) -> Vec<PatrolPath> {
+    let mut locs = vec![pos];
+    locs.extend(
+        get_entity_field::<Vec<GridPoint>>(entity, "Path")
+            .iter()
+            .map(grid_point_to_vec2),
+    );
+    let full_circle = get_entity_field::<bool>(entity, "FullCircle");
+    let facings = get_entity_field::<Vec<GridPoint>>(entity, "Facing")
+        .iter()
+        .enumerate()
+        .map(|(i, p)| {
+            let p = grid_point_to_vec2(p);
+            (p - locs[i])
+                .normalize()
+                .angle_between(vec2(1.0, 0.0))
+                .normalized_rads()
+        })
+        .collect::<Vec<_>>();
+    let mut wait_timings = get_entity_field::<Vec<f32>>(entity, "WaitTiming")
+        .iter()
+        .map(|&t| t as f64)
+        .collect::<Vec<_>>();
+    let walk_timings = get_entity_field::<Vec<f32>>(entity, "WalkTiming")
+        .iter()
+        .map(|&t| t as f64)
+        .collect::<Vec<_>>();
+    // an explicit speed replaces the walk timings, which may then be left empty
+    let speed = get_optional_entity_field::<f32>(entity, "PatrolSpeed");
+    let skin = get_optional_entity_field::<usize>(entity, "Skin");
+    let memory = get_optional_entity_field::<f32>(entity, "Memory");
+    let target = get_optional_entity_field::<bool>(entity, "Target").unwrap_or(false);
+    let cone_color =
+        get_optional_entity_field::<String>(entity, "ConeColor").map(|s| hex_str_to_color(&s));
+    let report_speed = get_optional_entity_field::<f32>(entity, "ReportSpeed");
+    let scan_arc = get_optional_entity_field::<f32>(entity, "ScanArc").map(|a| a.to_radians());
+    let scan_period = get_optional_entity_field::<f32>(entity, "ScanPeriod").map(|t| t as Time);
+    // a single node is a sentry that stands watch, so it never walks or has to
+    // wait for anything
+    let sentry = locs.len() == 1;
+    if sentry && wait_timings.is_empty() {
+        wait_timings.push(0.0);
+    }
+    let walk_timings = if sentry || (speed.is_some() && walk_timings.is_empty()) {
+        vec![None; locs.len()]
+    } else {
+        let mut walk_timings = walk_timings
+            .into_iter()
+            .map(|t| Some(t))
+            .collect::<Vec<_>>();
+        if !full_circle {
+            walk_timings.push(None);
+        }
+        walk_timings
+    };
+    let start = get_entity_field::<i32>(entity, "Start");
+    let extra_spawns = get_entity_field::<Vec<i32>>(entity, "ExtraSpawnAt");
+    assert!(
+        !extra_spawns.contains(&start),
+        "{}: extra_spawns contains start",
+        level_name
+    );
+    assert!(
+        locs.len() == facings.len()
+            && locs.len() == wait_timings.len()
+            && locs.len() == walk_timings.len(),
+        "{}, locs={}, facings={}, wait={}, walk={}",
+        level_name,
+        locs.len(),
+        facings.len(),
+        wait_timings.len(),
+        walk_timings.len()
+    );
+    let patrol_nodes = izip!(locs, facings, wait_timings, walk_timings)
+        .map(|(pos, facing, wait, walk)| PatrolNode {
+            pos,
+            facing,
+            wait,
+            walk,
+        })
+        .collect::<Vec<_>>();
+    [start]
+        .iter()
+        .chain(extra_spawns.iter())
+        .map(|&curr| PatrolPath {
+            nodes: patrol_nodes.clone(),
+            curr: curr as isize,
+            timer: None,
+            forwards: true,
+            full_circle,
+            speed,
+            skin,
+            memory,
+            target,
+            cone_color,
+            report_speed,
+            scan_arc,
+            scan_period,
+        })
+        .collect()
+}
+
+fn int_grid_cells(layer: &LayerInstance) -> impl Iterator<Item = (Rect, i64)> + '_ {
+    let grid_size = layer.grid_size as f32;
+    let layer_offset = vec2(
+        layer.px_total_offset_x as f32,
+        layer.px_total_offset_y as f32,
+    );
+    layer
+        .int_grid_csv
+        .iter()
+        .enumerate()
+        .map(move |(i, &value)| {
+            let x = i as i64 % layer.c_wid;
+            let y = i as i64 / layer.c_wid;
+            let pos = vec2(grid_size * x as f32, grid_size * y as f32) + layer_offset;
+            (Rect::new(pos.x, pos.y, grid_size, grid_size), value)
+        })
+}
+
+fn is_wall(layer: &LayerInstance, value: i64) -> bool {
+    value == 1 && layer.identifier == "Collisions"
+}
+
+fn is_shadow_caster(layer: &LayerInstance, value: i64) -> bool {
+    value == 1 && (layer.identifier == "ShadowCasters" || layer.identifier == "StructureGrid")
+}
+
+/// The walls and patrols of a level, loaded without any textures so it works without a window.
+pub struct LevelGeometry {
+    pub level_name: String,
+    pub bounds: Rect,
+    pub grid: Grid,
+    pub colliders: Vec<Rect>,
+    pub shadow_casters: Vec<Rect>,
+    // the position and facing of every node on each patrol path
+    pub patrol_paths: Vec<Vec<(Vec2, f32)>>,
+}
+
+pub fn load_level_geometry(ldtk_str: &str) -> Vec<LevelGeometry> {
+    let ldtk: Ldtk = serde_json::from_str(ldtk_str).unwrap();
+    ldtk.levels
+        .iter()
+        .map(|level| {
+            let mut ret = LevelGeometry {
+                level_name: level.identifier.clone(),
+                bounds: Rect::new(0.0, 0.0, level.px_wid as f32, level.px_hei as f32),
+                grid: Grid::default(),
+                colliders: Vec::new(),
+                shadow_casters: Vec::new(),
+                patrol_paths: Vec::new(),
+            };
+            for layer in level.layer_instances.as_ref().unwrap().iter() {
+                if layer.identifier == "Collisions" {
+                    ret.grid = Grid {
+                        cell_size: vec2(layer.grid_size as f32, layer.grid_size as f32),
+                        offset: vec2(
+                            layer.px_total_offset_x as f32,
+                            layer.px_total_offset_y as f32,
+                        ),
+                    };
+                }
+                for (cell, value) in int_grid_cells(layer) {
+                    if is_wall(layer, value) {
+                        ret.colliders.push(cell);
+                    }
+                    if is_shadow_caster(layer, value) {
+                        ret.shadow_casters.push(cell);
+                    }
+                }
+
+                let grid_size = layer.grid_size as f32;
+                let layer_offset = vec2(
+                    layer.px_total_offset_x as f32,
+                    layer.px_total_offset_y as f32,
+                );
+                let grid_point_to_vec2 = |p: &GridPoint| {
+                    vec2(p.cx as f32, p.cy as f32) * grid_size
+                        + vec2(grid_size, grid_size) / 2.0
+                        + layer_offset
+                };
+                for entity in layer
+                    .entity_instances
+                    .iter()
+                    .filter(|e| e.identifier == "PatrolPath")
+                {
+                    let size = vec2(entity.width as f32, entity.height as f32);
+                    let pos =
+                        vec2(entity.px[0] as f32, entity.px[1] as f32) + size / 2.0 + layer_offset;
+                    let mut locs = vec![pos];
+                    locs.extend(
+                        get_entity_field::<Vec<GridPoint>>(entity, "Path")
+                            .iter()
+                            .map(grid_point_to_vec2),
+                    );
+                    let facings = get_entity_field::<Vec<GridPoint>>(entity, "Facing");
+                    ret.patrol_paths.push(
+                        locs.iter()
+                            .zip(facings.iter())
+                            .map(|(&p, f)| {
+                                let facing = (grid_point_to_vec2(f) - p)
+                                    .normalize()
+                                    .angle_between(vec2(1.0, 0.0))
+                                    .normalized_rads();
+                                (p, facing)
+                            })
+                            .collect(),
+                    );
+                }
+            }
+            ret
+        })
+        .collect()
+}
+
+// a cell leads to the exit whose entity covers it, so an exit is drawn over its cells in the
+// editor. cells outside every exit's entity go to the nearest one
+fn lead_to_exits(triggers: &mut [Trigger], transitions: &[(Rect, String)]) {
+    for trigger in triggers
+        .iter_mut()
+        .filter(|t| t.ty == TriggerType::LevelTransition)
+    {
+        let center = trigger.rect.center();
+        let covering = transitions.iter().find(|(rect, _)| rect.contains(center));
+        trigger.destination = covering
+            .or_else(|| {
+                transitions.iter().min_by(|a, b| {
+                    (a.0.center() - center)
+                        .length()
+                        .partial_cmp(&(b.0.center() - center).length())
+                        .unwrap()
+                })
+            })
+            .map(|(_, name)| name.clone());
+    }
+}
+
 fn merge_triggers(mut triggers: Vec<Trigger>) -> Vec<Trigger> {
     let mut ret = Vec::new();
     while let Some(mut curr) = triggers.pop() {
-        while let Some((i, other)) = triggers
-            .iter()
-            .enumerate()
-            .find(|(_, other)| curr.rect.overlaps(&other.rect) && curr.ty == other.ty)
-        {
+        while let Some((i, other)) = triggers.iter().enumerate().find(|(_, other)| {
+            curr.rect.overlaps(&other.rect)
+                && curr.ty == other.ty
+                && curr.destination == other.destination
+        }) {
             curr.rect = curr.rect.combine_with(other.rect);
             triggers.remove(i);
         }
@@ -321,7 +696,9 @@ fn merge_tile_layer(sprites: &mut Vec<TileSprite>) -> Vec<TileSprite> {
     let mut ret = Vec::new();
     while let Some(mut curr) = sprites.pop() {
         while let Some((i, other)) = sprites.iter().enumerate().find(|(_, other)| {
-            curr.rect().overlaps(&other.rect()) && curr.sprite.src().overlaps(&other.sprite.src())
+            curr.rect().overlaps(&other.rect())
+                && curr.sprite.src().overlaps(&other.sprite.src())
+                && curr.opacity == other.opacity
         }) {
             curr.sprite.expand_src(&other.sprite);
             curr.pos = curr.pos.min(other.pos);
@@ -348,6 +725,30 @@ pub fn hex_str_to_color(s: &str) -> Color {
     Color { r, g, b, a }
 }
 
+fn get_level_field<T>(level: &schema::Level, id: &str) -> Option<T>
+where
+    T: DeserializeOwned,
+{
+    level
+        .field_instances
+        .iter()
+        .find(|f| f.identifier.as_str() == id)
+        .and_then(|f| f.value.clone())
+        .map(|v| serde_json::from_value(v).unwrap())
+}
+
+fn get_optional_entity_field<T>(entity: &EntityInstance, id: &str) -> Option<T>
+where
+    T: DeserializeOwned,
+{
+    entity
+        .field_instances
+        .iter()
+        .find(|f| f.identifier.as_str() == id)
+        .and_then(|f| f.value.clone())
+        .and_then(|v| serde_json::from_value(v).ok())
+}
+
 fn get_entity_field<T>(entity: &EntityInstance, id: &str) -> T
 where
     T: DeserializeOwned,
@@ -359,3 +760,118 @@ where
         .unwrap();
     serde_json::from_value(field.value.clone().unwrap()).unwrap()
 }
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use serde_json::{json, Value};
+
+    use super::*;
+
+    fn field(id: &str, value: Value) -> Value {
+        json!({
+            "__identifier": id,
+            "__type": "",
+            "__value": value,
+            "defUid": 0,
+            "realEditorValues": [],
+        })
+    }
+
+    /// A one-node patrol at (100, 100) looking along +x, parsed as load_ldtk would from a level
+    /// with an 8px grid, with `fields` added to the ones every patrol has.
+    pub(crate) fn one_node_patrol(fields: &[(&str, Value)]) -> PatrolPath {
+        let mut field_instances = vec![
+            field("Path", json!([])),
+            field("FullCircle", json!(false)),
+            field("Facing", json!([{ "cx": 20, "cy": 12 }])),
+            field("Start", json!(0)),
+            field("ExtraSpawnAt", json!([])),
+        ];
+        field_instances.extend(fields.iter().map(|(id, value)| field(id, value.clone())));
+        let entity: EntityInstance = serde_json::from_value(json!({
+            "__grid": [11, 11],
+            "__identifier": "PatrolPath",
+            "__pivot": [0.0, 0.0],
+            "__smartColor": "#FFFFFF",
+            "__tags": [],
+            "defUid": 0,
+            "fieldInstances": field_instances,
+            "height": 16,
+            "iid": "sentry",
+            "px": [92, 92],
+            "width": 16,
+        }))
+        .unwrap();
+        let grid_point_to_vec2 =
+            |p: &GridPoint| vec2(p.cx as f32, p.cy as f32) * 8.0 + vec2(4.0, 4.0);
+        let mut paths = patrol_paths(&entity, vec2(100.0, 100.0), &grid_point_to_vec2, "test");
+        assert_eq!(paths.len(), 1);
+        paths.pop().unwrap()
+    }
+
+    #[test]
+    fn a_one_node_patrol_waits_forever_and_never_walks() {
+        let sentry = one_node_patrol(&[("WaitTiming", json!([])), ("WalkTiming", json!([2.0]))]);
+        assert_eq!(sentry.nodes.len(), 1);
+        let node = &sentry.nodes[0];
+        assert_eq!((node.pos, node.facing), (vec2(100.0, 100.0), 0.0));
+        // the missing wait is filled in and the stray walk timing is dropped
+        assert_eq!((node.wait, node.walk), (0.0, None));
+
+        // a wait that was given is kept
+        let sentry = one_node_patrol(&[("WaitTiming", json!([3.0])), ("WalkTiming", json!([]))]);
+        assert_eq!((sentry.nodes[0].wait, sentry.nodes[0].walk), (3.0, None));
+    }
+
+    fn transition_cell(x: f32) -> Trigger {
+        Trigger {
+            rect: Rect::new(x, 0.0, 16.0, 16.0),
+            ty: TriggerType::LevelTransition,
+            destination: None,
+        }
+    }
+
+    #[test]
+    fn exits_next_to_each_other_keep_their_own_destinations() {
+        // a row of four transition cells, the left two by one exit and the right two by another
+        let mut triggers = (0..4)
+            .map(|i| transition_cell(i as f32 * 16.0))
+            .collect::<Vec<_>>();
+        let transitions = [
+            (Rect::new(-32.0, 0.0, 16.0, 16.0), "west".to_string()),
+            (Rect::new(80.0, 0.0, 16.0, 16.0), "east".to_string()),
+        ];
+        lead_to_exits(&mut triggers, &transitions);
+        let mut merged = merge_triggers(triggers);
+        merged.sort_by(|a, b| a.rect.x.partial_cmp(&b.rect.x).unwrap());
+
+        assert_eq!(merged.len(), 2);
+        assert_eq!(merged[0].destination.as_deref(), Some("west"));
+        assert_eq!(merged[0].rect, Rect::new(0.0, 0.0, 32.0, 16.0));
+        assert_eq!(merged[1].destination.as_deref(), Some("east"));
+        assert_eq!(merged[1].rect, Rect::new(32.0, 0.0, 32.0, 16.0));
+    }
+    #[test]
+    fn cells_lead_to_the_exit_drawn_over_them_even_when_another_is_nearer() {
+        let mut triggers = (0..4)
+            .map(|i| transition_cell(i as f32 * 16.0))
+            .collect::<Vec<_>>();
+        // the west exit reaches down a long corridor, so its center is far from its cells, and
+        // the east exit's center sits right next to them
+        let transitions = [
+            (Rect::new(0.0, -300.0, 32.0, 316.0), "west".to_string()),
+            (Rect::new(32.0, 0.0, 32.0, 16.0), "east".to_string()),
+        ];
+        lead_to_exits(&mut triggers, &transitions);
+        let destinations = triggers
+            .iter()
+            .map(|t| t.destination.as_deref().unwrap())
+            .collect::<Vec<_>>();
+        assert_eq!(destinations, ["west", "west", "east", "east"]);
+
+        // a cell no exit covers still goes to the nearest one
+        let mut stray = [transition_cell(96.0)];
+        lead_to_exits(&mut stray, &transitions);
+        assert_eq!(stray[0].destination.as_deref(), Some("east"));
+    }
+}
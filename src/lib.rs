@@ -0,0 +1,4634 @@
+use bindata::preload_textures;
+use lazy_static::lazy_static;
+use macroquad::rand::ChooseRandom;
+use pathfinder::Pathfinder;
+use serde::Serialize;
+use settings::{settings, AimLock, Settings};
+use sounds::{
+    load_sounds, play, play_at, set_handle_volume, set_master_volume, stop_all, stop_handle,
+    update_loops, SoundHandle,
+};
+use sprite::{anim_rects, Sprite, SpriteMap, SpriteSheet};
+use std::{
+    borrow::Cow,
+    cell::RefCell,
+    collections::hash_map::DefaultHasher,
+    f32::consts::TAU,
+    hash::{Hash, Hasher},
+    ops::Deref,
+    sync::{
+        atomic::{AtomicBool, AtomicUsize, Ordering},
+        Mutex,
+    },
+    vec,
+};
+use text_helpers::{draw_text_aligned, TextAlign};
+use ui::{Ui, UiAction};
+
+use draw_utils::{draw_dotted_line, draw_ground_shadow, DebugDrawer};
+use events::{drain_events, emit, GameEvent};
+use geometry_utils::{
+    corner_normal, intersect_lines, line_rect_intersect, rotate_vec2, segment_distance,
+    shape_preserving_rect_merge, slide_rect, triangle_contains, GeoUtilsFloatExts, CORNER_EPSILON,
+};
+use ldtk::{
+    get_level_indices, load_ldtk, OneWayWindow, PopUp, PopUpGate, TileSprite, WinCondition,
+};
+use macroquad::prelude::*;
+use materials::shadow_postprocess;
+use quadtree::QuadTree;
+use replay::{
+    advance_playback, is_key_down, is_key_pressed, is_key_released, is_mouse_button_down,
+    is_mouse_button_pressed, is_mouse_button_released, mouse_position, replay_path, start_playback,
+    Recording,
+};
+use save::{save_data, Medal};
+
+mod bindata;
+mod draw_utils;
+pub mod events;
+pub mod geometry_utils;
+pub mod ldtk;
+mod materials;
+pub mod pathfinder;
+pub mod quadtree;
+mod replay;
+mod save;
+mod settings;
+mod sounds;
+pub mod sprite;
+mod text_helpers;
+mod texturepacker;
+mod ui;
+
+#[cfg(target_arch = "wasm32")]
+mod getrandom_on_web;
+
+// #E04D49
+const MY_RED: Color = Color::new(0.878, 0.302, 0.286, 1.0);
+// #CDCBE4
+const MY_WHITE: Color = Color::new(0.804, 0.796, 0.894, 1.0);
+
+pub type Time = f64;
+
+// seconds of continuous sight for a guard to go from unaware to reporting at the edge of its cone
+const ASSESS_DURATION: f32 = 2.0;
+
+// one step of a suspicious guard's assessment: t climbs by dt / ASSESS_DURATION while the
+// suspect stays in sight, and the guard reports (None) once t reaches the suspect's distance
+// as a fraction of the detection radius, so closer suspects are reported sooner
+fn assess_step(t: f32, dist: f32, detection_radius: f32, dt: f32) -> Option<f32> {
+    let dist_t = dist / detection_radius;
+    let t = t + dt / ASSESS_DURATION;
+    if t >= dist_t.min(1.0) {
+        None
+    } else {
+        Some(t)
+    }
+}
+
+// how close the player's footsteps have to be for a guard to hear them, and how much further
+// they carry while dragging a body
+const HEARING_RADIUS: f32 = 64.0;
+const CARRYING_NOISE_SCALE: f32 = 1.5;
+// how suspicious a guard that only heard the player is, it goes looking without seeing anything
+const HEARD_SUSPICION: f32 = 0.5;
+
+// seconds for a waiting guard to sweep across its scan arc and back
+const SCAN_PERIOD: Time = 4.0;
+
+// seconds of standing still before a character looks around
+const IDLE_LOOK_AROUND_TIME: Time = 5.0;
+
+// how long before a waiting guard heads off that it shows which way it's about to turn
+const TURN_TELEGRAPH_TIME: Time = 0.5;
+
+// the longest step the simulation takes in one frame, so stalls can't teleport anything
+const MAX_DT: f32 = 0.25;
+// macroquad doesn't pass focus or minimize events on, so this only catches a window that stalled,
+// as a minimized one does on some platforms and a background tab does on web. a window that
+// loses focus but keeps drawing never pauses, and the game stays paused until the player resumes
+const FOCUS_LOST_FRAME_TIME: f32 = 0.5;
+
+// how much slower the game runs right after a kill, how long that lasts and how long it takes
+// to speed back up, all in real time
+const SLOW_MO_SCALE: f32 = 0.35;
+const SLOW_MO_HOLD: Time = 0.2;
+const SLOW_MO_RAMP: Time = 0.2;
+
+// the time scale `elapsed` seconds after a kill, or None once it has worn off
+fn slow_mo_scale(elapsed: Time) -> Option<f32> {
+    if elapsed < SLOW_MO_HOLD {
+        Some(SLOW_MO_SCALE)
+    } else if elapsed < SLOW_MO_HOLD + SLOW_MO_RAMP {
+        let t = ((elapsed - SLOW_MO_HOLD) / SLOW_MO_RAMP) as f32;
+        Some(SLOW_MO_SCALE + (1.0 - SLOW_MO_SCALE) * t)
+    } else {
+        None
+    }
+}
+
+trait Draw {
+    fn draw(&self);
+    fn sort_order(&self) -> f32;
+}
+
+// sprites are drawn this far above their position
+const SPRITE_Y_OFFSET: f32 = 6.0;
+// how far each character's emotes are nudged from just above the middle of their sprite, for
+// art whose head doesn't reach the top of its frame
+const PLAYER_EMOTE_OFFSET: Vec2 = vec2(0.0, 0.0);
+const ENEMY_EMOTE_OFFSET: Vec2 = vec2(0.0, 0.0);
+// how much is trimmed off each side of a sprite's rect (left, top, right, bottom) for
+// colliding with walls, a little smaller than the body so it doesn't snag on corners
+const COLLISION_INSETS: [f32; 4] = [8.0, 10.0, 8.0, 10.0];
+// the same for being seen by guards, which should match the body as it's drawn
+const DETECTION_INSETS: [f32; 4] = [0.0, 0.0, 0.0, 0.0];
+
+fn inset_rect(rect: Rect, [left, top, right, bottom]: [f32; 4]) -> Rect {
+    Rect {
+        x: rect.x + left,
+        y: rect.y + top,
+        w: rect.w - left - right,
+        h: rect.h - top - bottom,
+    }
+}
+
+// the footprint used for walls, triggers and pathing
+fn collision_rect(rect: Rect) -> Rect {
+    inset_rect(rect, COLLISION_INSETS)
+}
+
+// where the sprite is actually drawn, so guards see the whole visible body
+fn detection_rect(rect: Rect) -> Rect {
+    let drawn = Rect {
+        y: rect.y - SPRITE_Y_OFFSET,
+        ..rect
+    };
+    inset_rect(drawn, DETECTION_INSETS)
+}
+
+#[derive(Debug, Clone)]
+struct Emotes {
+    emote_smap: SpriteMap,
+    base_intro: Sprite,
+    base_final: Sprite,
+    // nudges the emote away from where it sits by default, centered just above the head
+    offset: Vec2,
+
+    intial_phase: RefCell<bool>,
+    last_drawn: RefCell<String>,
+}
+
+impl Emotes {
+    fn new(emote_smap: SpriteMap) -> Self {
+        let base_intro = emote_smap.get("base_intro").clone();
+        let base_final = emote_smap.get("base_final").clone();
+        Self {
+            emote_smap,
+            base_intro,
+            base_final,
+            offset: vec2(0.0, 0.0),
+            last_drawn: RefCell::new("".to_owned()),
+            intial_phase: RefCell::new(true),
+        }
+    }
+
+    fn with_offset(self, offset: Vec2) -> Self {
+        Self { offset, ..self }
+    }
+
+    // the top left of an emote for a body whose sprite is drawn at `sprite_top_left`
+    fn anchor(&self, sprite_top_left: Vec2, sprite_size: Vec2) -> Vec2 {
+        let size = self.base_final.size();
+        vec2(
+            sprite_top_left.x + (sprite_size.x - size.x) / 2.0,
+            sprite_top_left.y - size.y,
+        ) + self.offset
+    }
+
+    fn draw(&self, pos: Vec2, name: &str) {
+        if self.draw_bubble(pos, name) {
+            self.emote_smap.get(name).draw(pos);
+        }
+    }
+
+    // "..." isn't on the emote sheet, so its dots are drawn into the bubble, one more every
+    // third of a second
+    fn draw_ellipsis(&self, pos: Vec2) {
+        if self.draw_bubble(pos, "ellipsis") {
+            let count = (get_time() * 3.0) as usize % 3 + 1;
+            for i in 0..count {
+                let p = pos + vec2(10.0 + i as f32 * 6.0, 14.0);
+                draw_circle(p.x, p.y, 1.5, Color::from_hex(0x3a3a50));
+            }
+        }
+    }
+
+    // the bubble pops up whenever the emote changes, and returns whether it's ready for the
+    // emote to be drawn in it
+    fn draw_bubble(&self, pos: Vec2, name: &str) -> bool {
+        if *self.last_drawn.borrow() != name {
+            *self.intial_phase.borrow_mut() = true;
+            *self.last_drawn.borrow_mut() = name.to_string();
+            self.base_intro.reset();
+            self.base_final.reset();
+        }
+        if *self.intial_phase.borrow() {
+            if self.base_intro.about_to_loop() {
+                *self.intial_phase.borrow_mut() = false;
+            }
+            self.base_intro.draw(pos);
+            false
+        } else {
+            self.base_final.draw(pos);
+            true
+        }
+    }
+
+    fn reset(&self) {
+        *self.intial_phase.borrow_mut() = true;
+        *self.last_drawn.borrow_mut() = "".to_owned();
+        self.base_intro.reset();
+        self.base_final.reset();
+    }
+}
+
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+enum ArrowType {
+    Lethal,
+    // tags the guard it hits so they can be tracked through walls for a while
+    Tracker,
+}
+
+#[derive(Debug, Eq, PartialEq)]
+enum PlayerState {
+    Idle,
+    Moving,
+    Caught,
+}
+
+// draws the bow without a mouse, see Player::keyboard_aim
+const AIM_KEY: KeyCode = KeyCode::J;
+
+#[derive(Debug)]
+struct Player {
+    pos: Vec2,
+    strike_cone: Option<(Vec2, f32, Vec<Intersection>)>,
+    smap: SpriteMap,
+    emotes: Emotes,
+    last_v: Vec2,
+    // the sign of the last sideways movement, which picks the sitting pose
+    last_dir_x: f32,
+    is_moving: bool,
+    // the iter the strike animation is currently on, so we know when to stop
+    state: PlayerState,
+    // the id of the carried body and its offset from the player
+    carrying: Option<(EnemyId, Vec2)>,
+    pickup_radius: f32,
+    walk_speed: f32,
+    carry_speed: f32,
+    // carrying a body for longer than this slows the player down until it is dropped,
+    // or never if None
+    max_carry_time: Option<Time>,
+    carry_time: Time,
+    detected: bool,
+    seen_mouse_pressed: bool,
+    // the aim angle while drawing the bow from the keyboard, None when the aim key isn't held
+    keyboard_aim: Option<f32>,
+    bow_charge_duration: Time,
+    // an under-charged shot was released, and the charge is draining before the aim clears
+    cancelling: bool,
+    // the run animation frame we last checked for a footstep
+    last_run_frame: Option<usize>,
+    arrow_type: ArrowType,
+    // how long we've been standing still, which plays a look around once it passes
+    // IDLE_LOOK_AROUND_TIME
+    idle_time: Time,
+    // the guard that found us, highlighted until the level resets
+    caught_by: Option<EnemyId>,
+}
+
+impl Player {
+    fn new(pos: Vec2, smap: SpriteMap, emotes: Emotes) -> Self {
+        Self {
+            pos,
+            strike_cone: None,
+            smap,
+            emotes,
+            last_v: vec2(0.0, 0.0),
+            last_dir_x: 1.0,
+            is_moving: false,
+            state: PlayerState::Idle,
+            carrying: None,
+            pickup_radius: 48.0,
+            walk_speed: 100.0,
+            carry_speed: 75.0,
+            max_carry_time: settings().max_carry_time,
+            carry_time: 0.0,
+            detected: false,
+            seen_mouse_pressed: false,
+            keyboard_aim: None,
+            bow_charge_duration: 0.5,
+            cancelling: false,
+            last_run_frame: None,
+            arrow_type: ArrowType::Lethal,
+            idle_time: 0.0,
+            caught_by: None,
+        }
+    }
+
+    fn caught(&mut self, found_dead_body: bool, by: EnemyId) {
+        if !debug() {
+            if !self.detected {
+                emit(GameEvent::PlayerDetected);
+                self.caught_by = Some(by);
+            }
+            if !found_dead_body {
+                self.state = PlayerState::Caught;
+            }
+            self.detected = true;
+        }
+    }
+
+    // how loud the player is as a multiple of a guard's hearing radius, None when silent. only
+    // running makes noise for now
+    fn noise(&self) -> Option<f32> {
+        match self.state {
+            PlayerState::Moving if self.carrying.is_some() => Some(CARRYING_NOISE_SCALE),
+            PlayerState::Moving => Some(1.0),
+            _ => None,
+        }
+    }
+
+    fn curr_sprite(&self) -> &Sprite {
+        match self.state {
+            PlayerState::Caught => self.smap.get(sit_name(self.last_dir_x)),
+            PlayerState::Moving => self.smap.get(vel_to_name("run", self.last_v)),
+            _ if self.idle_time >= IDLE_LOOK_AROUND_TIME => {
+                self.smap.get(vel_to_name("look_around", self.last_v))
+            }
+            _ => self.smap.get(vel_to_name("idle", self.last_v)),
+        }
+    }
+
+    fn rect(&self) -> Rect {
+        let (width, height) = self.curr_sprite().size().into();
+        Rect::new(
+            self.pos.x - width / 2.0,
+            self.pos.y - height / 2.0,
+            width,
+            height,
+        )
+    }
+
+    fn collision_rect(&self) -> Rect {
+        collision_rect(self.rect())
+    }
+
+    fn detection_rect(&self) -> Rect {
+        detection_rect(self.rect())
+    }
+
+    fn strike(
+        &mut self,
+        projectiles: &mut Vec<Projectile>,
+        enemies: &mut [Enemy],
+        colliders: &Colliders,
+    ) {
+        assert!(self.strike_cone.is_some());
+        let at_cap = projectiles.len() >= settings().max_projectiles;
+        if at_cap && settings().refuse_shots_at_cap {
+            play("wrong", 1.0, false);
+            self.cancelling = true;
+            return;
+        }
+        play("arrow_shoot", 1.0, false);
+        for e in enemies.iter_mut() {
+            e.hightlight = false;
+        }
+        let (mouse_pos, t, ints) = self.strike_cone.as_ref().unwrap();
+        projectiles.push(Projectile::new(self.pos, &ints, self.arrow_type));
+        emit(GameEvent::ArrowFired);
+
+        // an early release scatters two extra arrows, less so the longer the bow was drawn
+        if settings().spread_shot && *t < 1.0 {
+            let max_spread = 20.0f32.to_radians();
+            let spread = max_spread * (1.0 - *t);
+            let dir = self.aim_dir(*mouse_pos);
+            for angle in [-spread, spread] {
+                let ints = get_intersections(self.pos, rotate_vec2(dir, angle), enemies, colliders);
+                projectiles.push(Projectile::new(self.pos, &ints, self.arrow_type));
+                emit(GameEvent::ArrowFired);
+            }
+        }
+        self.strike_cone = None;
+    }
+
+    fn set_strike_cone(
+        &mut self,
+        new_strike_cone: Vec2,
+        enemies: &mut [Enemy],
+        colliders: &Colliders,
+        dt: f32,
+    ) {
+        for e in enemies.iter_mut() {
+            e.hightlight = false;
+        }
+        if let Some((mouse_pos, t, _)) = self.strike_cone.as_mut() {
+            *mouse_pos = new_strike_cone;
+            *t = (*t + dt / self.bow_charge_duration as f32).min(1.0);
+        } else {
+            self.strike_cone = Some((new_strike_cone, 0.0, Vec::new()));
+        }
+        let dir = self.aim_dir(self.strike_cone.as_ref().unwrap().0);
+        let ints = get_intersections(self.pos, dir, enemies, colliders);
+        self.strike_cone.as_mut().unwrap().2 = ints;
+    }
+
+    // the direction arrows actually fly when aiming at `mouse_pos`
+    fn aim_dir(&self, mouse_pos: Vec2) -> Vec2 {
+        let dir = (mouse_pos - self.pos).normalize();
+        if settings().inverted_aim {
+            -dir
+        } else {
+            dir
+        }
+    }
+
+    // the closest body within the pickup radius that isn't on the other side of a wall
+    fn body_in_reach<'a>(
+        &self,
+        enemies: &'a [Enemy],
+        pathfinder: &Pathfinder,
+    ) -> Option<&'a Enemy> {
+        // a thin ray from the player, so bodies right up against a wall still count
+        let reach = Rect::new(self.pos.x - 1.0, self.pos.y - 1.0, 2.0, 2.0);
+        enemies
+            .iter()
+            .filter(|e| {
+                e.dead()
+                    && (e.pos - self.pos).length() < self.pickup_radius
+                    && !pathfinder.is_direct_path_blocked(reach, e.pos)
+            })
+            .min_by(|a, b| {
+                (a.pos - self.pos)
+                    .length()
+                    .partial_cmp(&(b.pos - self.pos).length())
+                    .unwrap()
+            })
+    }
+
+    // lets go of the carried body, nudging it out of any geometry so it can be picked up again
+    fn drop_body(&mut self, enemies: &mut [Enemy], pathfinder: &Pathfinder) {
+        if let Some((id, _)) = self.carrying.take() {
+            if let Some(e) = enemies.iter_mut().find(|e| e.id == id) {
+                let rect = e.collision_rect();
+                if pathfinder.is_rect_colliding(rect) {
+                    let search_radius = 4;
+                    if let Some(pos) =
+                        pathfinder.nearest_walkable(e.pos, rect.size(), search_radius)
+                    {
+                        e.pos = pos;
+                    }
+                }
+            }
+        }
+    }
+
+    fn go(&mut self, v: Vec2, colliders: &[Rect]) {
+        self.last_v = v;
+        if v.x != 0.0 {
+            self.last_dir_x = v.x.signum();
+        }
+        let v = slide_rect(self.collision_rect(), v, colliders);
+        self.is_moving = true;
+        self.pos += v;
+        // a direction-locked aim point walks along with us
+        if settings().aim_lock == AimLock::Direction {
+            if let Some((target, _, _)) = self.strike_cone.as_mut() {
+                *target += v;
+            }
+        }
+    }
+
+    fn tick(
+        &mut self,
+        camera: &mut Camera2D,
+        colliders: &Colliders,
+        pathfinder: &Pathfinder,
+        projectiles: &mut Vec<Projectile>,
+        enemies: &mut [Enemy],
+        dt: f32,
+    ) {
+        self.is_moving = false;
+        // the carried body may have been removed since we picked it up
+        if let Some((id, _)) = self.carrying {
+            if !enemies.iter().any(|e| e.id == id && e.dead()) {
+                self.carrying = None;
+            }
+        }
+        if let Some(max_carry_time) = self.max_carry_time {
+            if self.carrying.is_some() {
+                self.carry_time += dt as Time;
+                if self.carry_time >= max_carry_time {
+                    play("hit", 1.0, false);
+                    self.drop_body(enemies, pathfinder);
+                }
+            } else {
+                self.carry_time = (self.carry_time - dt as Time).max(0.0);
+            }
+        }
+        let speed = if self.carrying.is_none() {
+            self.walk_speed
+        } else {
+            // the most tired the player gets is 60% of the carry speed
+            self.carry_speed * (0.6 + 0.4 * self.stamina())
+        };
+        match self.state {
+            PlayerState::Caught => {}
+            _ => {
+                self.state = PlayerState::Idle;
+
+                if self.cancelling {
+                    let drain_duration = 0.15;
+                    let (_, t, _) = self.strike_cone.as_mut().unwrap();
+                    *t -= dt / drain_duration;
+                    if *t <= 0.0
+                        || is_mouse_button_pressed(MouseButton::Left)
+                        || is_key_pressed(AIM_KEY)
+                    {
+                        self.strike_cone = None;
+                        self.cancelling = false;
+                    }
+                } else if self.strike_cone.is_some()
+                    && (is_mouse_button_released(MouseButton::Left) || is_key_released(AIM_KEY))
+                {
+                    if self.strike_cone.as_ref().unwrap().1 == 1.0 || settings().spread_shot {
+                        self.strike(projectiles, enemies, colliders);
+                    } else {
+                        play("wrong", 1.0, false);
+                        self.cancelling = true;
+                    }
+                }
+                // the mouse wins when both aim at once, so the bow only draws once a frame
+                let mut aim_target = None;
+                self.seen_mouse_pressed =
+                    self.seen_mouse_pressed || is_mouse_button_pressed(MouseButton::Left);
+                if self.seen_mouse_pressed
+                    && !self.cancelling
+                    && is_mouse_button_down(MouseButton::Left)
+                {
+                    aim_target = Some(match (&self.strike_cone, settings().aim_lock) {
+                        (Some((target, _, _)), AimLock::Direction) => *target,
+                        _ => camera.screen_to_world(mouse_position().into()),
+                    });
+                }
+
+                // holding the aim key draws the bow towards the way we last faced, and the
+                // arrow keys turn it
+                if is_key_pressed(AIM_KEY) {
+                    self.keyboard_aim = Some(if self.last_dir_x < 0.0 {
+                        std::f32::consts::PI
+                    } else {
+                        0.0
+                    });
+                }
+                if !is_key_down(AIM_KEY) {
+                    self.keyboard_aim = None;
+                }
+                if let Some(angle) = self.keyboard_aim.as_mut() {
+                    let turn_speed = 2.5;
+                    if is_key_down(KeyCode::Left) {
+                        *angle -= turn_speed * dt;
+                    }
+                    if is_key_down(KeyCode::Right) {
+                        *angle += turn_speed * dt;
+                    }
+                    if !self.cancelling && aim_target.is_none() {
+                        aim_target = Some(self.pos + Vec2::from_angle(*angle) * 64.0);
+                    }
+                }
+                if let Some(target) = aim_target {
+                    self.set_strike_cone(target, enemies, colliders, dt);
+                }
+
+                if is_key_pressed(KeyCode::Tab) {
+                    play("menu_tick", 1.0, false);
+                    self.arrow_type = match self.arrow_type {
+                        ArrowType::Lethal => ArrowType::Tracker,
+                        ArrowType::Tracker => ArrowType::Lethal,
+                    };
+                }
+
+                if is_key_pressed(KeyCode::E) {
+                    if self.carrying.is_none() {
+                        if let Some(e) = self.body_in_reach(enemies, pathfinder) {
+                            play("hit", 1.0, false);
+                            self.carrying = Some((e.id, e.pos - self.pos));
+                            emit(GameEvent::BodyPickedUp(e.id));
+                        }
+                    } else {
+                        play("hit", 1.0, false);
+                        self.drop_body(enemies, pathfinder);
+                    }
+                }
+
+                let mut v = vec2(0.0, 0.0);
+                let locked = self.strike_cone.is_some()
+                    && !self.cancelling
+                    && settings().aim_lock == AimLock::Movement;
+                if !locked {
+                    if is_key_down(KeyCode::W) {
+                        v.y = -1.0;
+                    }
+                    if is_key_down(KeyCode::S) {
+                        v.y = 1.0;
+                    }
+                    if is_key_down(KeyCode::A) {
+                        v.x = -1.0;
+                    }
+                    if is_key_down(KeyCode::D) {
+                        v.x = 1.0;
+                    }
+                }
+                if v.length() > 0.0 {
+                    self.state = PlayerState::Moving;
+                    v = v.normalize() * speed * dt;
+                    self.go(v, colliders);
+
+                    // the frames of the run animation where a foot hits the ground
+                    let footstep_frames = [0, 3];
+                    let frame = self.curr_sprite().curr_frame();
+                    if self.last_run_frame != Some(frame) && footstep_frames.contains(&frame) {
+                        play("footstep", 1.0, false);
+                    }
+                    self.last_run_frame = Some(frame);
+                    // the body slides along walls rather than being dragged through them
+                    if let Some((id, off)) = self.carrying {
+                        if let Some(e) = enemies.iter_mut().find(|e| e.id == id) {
+                            let v = self.pos + off - e.pos;
+                            e.pos += slide_rect(e.collision_rect(), v, colliders);
+                        }
+                    }
+                }
+            }
+        }
+        if !self.is_moving {
+            self.last_run_frame = None;
+        }
+
+        let idle = self.state == PlayerState::Idle
+            && self.strike_cone.is_none()
+            && settings().player_look_around;
+        if !idle {
+            self.idle_time = 0.0;
+        } else if self.idle_time >= IDLE_LOOK_AROUND_TIME {
+            // the look around plays once and then it's back to the plain idle
+            if self.curr_sprite().about_to_loop() {
+                self.idle_time = 0.0;
+            }
+        } else {
+            self.idle_time += dt as Time;
+        }
+    }
+
+    fn draw_bow(&self, camera: &Camera2D) {
+        if let Some((mouse_pos, strike_t, ints)) = &self.strike_cone {
+            let dash_scale = dash_scale(camera);
+            let tr = self.collision_rect();
+            let bar_off = vec2(-16.0, 7.0);
+            let bar_pos = vec2(tr.x, tr.y + tr.h) + bar_off;
+            let bar_width = tr.w + bar_off.x.abs() * 2.0;
+            let bar_height = 6.0;
+            draw_rectangle_lines(
+                bar_pos.x - 1.0,
+                bar_pos.y - 1.0,
+                bar_width + 2.0,
+                bar_height + 2.0,
+                1.0,
+                BLACK,
+            );
+            draw_rectangle(
+                bar_pos.x,
+                bar_pos.y,
+                bar_width * strike_t,
+                bar_height,
+                WHITE,
+            );
+
+            let (_, height) = self.curr_sprite().size().into();
+            let max_len = 48.0;
+            let len = strike_t * max_len;
+            let mouse_dir = self.aim_dir(*mouse_pos) * len;
+            // grayed out when the shot wouldn't reach any guard
+            let connects = ints.iter().any(|i| matches!(i.entity, IsectType::Enemy(_)));
+            let line_color = if connects { WHITE } else { GRAY };
+            draw_dotted_line(
+                self.pos.x,
+                self.pos.y,
+                self.pos.x + mouse_dir.x,
+                self.pos.y + mouse_dir.y,
+                3.0,
+                5.0 * dash_scale,
+                3.0 * dash_scale,
+                BLACK,
+            );
+            draw_dotted_line(
+                self.pos.x,
+                self.pos.y,
+                self.pos.x + mouse_dir.x,
+                self.pos.y + mouse_dir.y,
+                2.0,
+                4.0 * dash_scale,
+                4.0 * dash_scale,
+                line_color,
+            );
+            let angle = mouse_dir.angle_between(vec2(0.0, -1.0)).to_degrees();
+            let arc_len_deg = 90.0;
+            draw_arc(
+                self.pos.x,
+                self.pos.y,
+                32,
+                height,
+                180.0 - angle + arc_len_deg / 2.0 - 1.0,
+                3.0,
+                arc_len_deg + 2.0,
+                BLACK,
+            );
+            draw_arc(
+                self.pos.x,
+                self.pos.y,
+                32,
+                height,
+                180.0 - angle + arc_len_deg / 2.0,
+                2.0,
+                arc_len_deg,
+                line_color,
+            );
+
+            for (idx, int) in ints.iter().enumerate() {
+                let pos = int.pos;
+                match int.entity {
+                    IsectType::Enemy(_) => {
+                        draw_circle(pos.x, pos.y, 3.0, BLACK);
+                        draw_circle(pos.x, pos.y, 2.0, WHITE);
+                    }
+                    IsectType::Collider(_) if idx < ints.len() - 1 => {
+                        let new_dir = int.new_dir;
+                        let old_dir = rotate_vec2(int.normal, -int.normal.angle_between(new_dir));
+                        draw_dotted_line(
+                            pos.x,
+                            pos.y,
+                            pos.x + new_dir.x * 25.0,
+                            pos.y + new_dir.y * 25.0,
+                            3.0,
+                            5.0 * dash_scale,
+                            3.0 * dash_scale,
+                            BLACK,
+                        );
+                        draw_dotted_line(
+                            pos.x,
+                            pos.y,
+                            pos.x + old_dir.x * 25.0,
+                            pos.y + old_dir.y * 25.0,
+                            3.0,
+                            5.0 * dash_scale,
+                            3.0 * dash_scale,
+                            BLACK,
+                        );
+                        draw_dotted_line(
+                            pos.x,
+                            pos.y,
+                            pos.x + new_dir.x * 25.0,
+                            pos.y + new_dir.y * 25.0,
+                            2.0,
+                            4.0 * dash_scale,
+                            4.0 * dash_scale,
+                            line_color,
+                        );
+                        draw_dotted_line(
+                            pos.x,
+                            pos.y,
+                            pos.x + old_dir.x * 25.0,
+                            pos.y + old_dir.y * 25.0,
+                            2.0,
+                            4.0 * dash_scale,
+                            4.0 * dash_scale,
+                            line_color,
+                        );
+                    }
+                    _ => {}
+                }
+            }
+
+            // a crosshair where the arrow will end up, red when it would kill a guard
+            if let Some(last) = ints.last() {
+                let on_guard = matches!(last.entity, IsectType::Enemy(_));
+                let color = if on_guard && self.arrow_type == ArrowType::Lethal {
+                    MY_RED
+                } else {
+                    line_color
+                };
+                let pos = last.pos;
+                let (gap, arm) = (3.0, 5.0);
+                for dir in [
+                    vec2(1.0, 0.0),
+                    vec2(-1.0, 0.0),
+                    vec2(0.0, 1.0),
+                    vec2(0.0, -1.0),
+                ] {
+                    let (from, to) = (pos + dir * gap, pos + dir * (gap + arm));
+                    draw_line(from.x, from.y, to.x, to.y, 3.0, BLACK);
+                    draw_line(from.x, from.y, to.x, to.y, 1.5, color);
+                }
+            }
+        }
+    }
+
+    // from 1 when rested to 0 when a carried body has to be dropped
+    fn stamina(&self) -> f32 {
+        match self.max_carry_time {
+            Some(max_carry_time) => (1.0 - self.carry_time / max_carry_time).max(0.0) as f32,
+            None => 1.0,
+        }
+    }
+
+    fn draw_emotes(&self) {
+        let size = self.curr_sprite().size();
+        let mut sprite_top_left = self.pos - size / 2.0;
+        sprite_top_left.y -= SPRITE_Y_OFFSET;
+        let top_left = self.emotes.anchor(sprite_top_left, size);
+        if self.carrying.is_some() {
+            self.emotes.draw(top_left, "sweat");
+        } else {
+            self.emotes.reset();
+        }
+    }
+}
+
+impl Draw for Player {
+    fn draw(&self) {
+        draw_ground_shadow(self.collision_rect(), 0.0);
+
+        let size = self.curr_sprite().size();
+        let mut top_left = self.pos - size / 2.0;
+        top_left.y -= SPRITE_Y_OFFSET;
+        self.curr_sprite().draw(top_left);
+        {
+            let collision_rect = self.collision_rect();
+            debug_draw(move || {
+                draw_rectangle_lines(top_left.x, top_left.y, size.x, size.y, 1.0, RED);
+                draw_rectangle_lines(
+                    collision_rect.x,
+                    collision_rect.y,
+                    collision_rect.w,
+                    collision_rect.h,
+                    1.0,
+                    BLUE,
+                );
+            });
+        }
+    }
+
+    fn sort_order(&self) -> f32 {
+        self.pos.y + self.curr_sprite().size().y / 2.0
+    }
+}
+
+#[derive(Debug)]
+pub struct Polygon {
+    pub vertices: Vec<Vertex>,
+    pub indices: Vec<u16>,
+}
+
+impl Polygon {
+    pub fn contains(&self, p: Vec2) -> bool {
+        for chunk in self.indices.chunks_exact(3) {
+            let a: Vec2 = self.vertices[chunk[0] as usize].position.xy();
+            let b: Vec2 = self.vertices[chunk[1] as usize].position.xy();
+            let c: Vec2 = self.vertices[chunk[2] as usize].position.xy();
+            if triangle_contains(p, a, b, c) {
+                return true;
+            }
+        }
+        false
+    }
+
+    pub fn draw_outline(&self, color: Color, thickness: f32, _t: f32) {
+        let first = self.vertices[self.indices[0] as usize].position.xy();
+        let second = self.vertices[self.indices[1] as usize].position.xy();
+        let last = self.vertices[self.indices[self.indices.len() - 1] as usize]
+            .position
+            .xy();
+
+        draw_line(first.x, first.y, second.x, second.y, thickness, color);
+        draw_line(first.x, first.y, last.x, last.y, thickness, color);
+
+        for idxs in self.indices.chunks_exact(3) {
+            let b = self.vertices[idxs[1] as usize].position.xy();
+            let c = self.vertices[idxs[2] as usize].position.xy();
+
+            draw_line(b.x, b.y, c.x, c.y, 0.5, color);
+        }
+    }
+
+    pub fn draw_eye(&self, color: Color, thickness: f32, radius: f32) {
+        let first = self.vertices[self.indices[0] as usize].position.xy();
+        let second = self.vertices[self.indices[1] as usize].position.xy();
+        let last = self.vertices[self.indices[self.indices.len() - 1] as usize]
+            .position
+            .xy();
+
+        let second = first + (second - first).normalize() * radius;
+        let last = first + (last - first).normalize() * radius;
+        draw_line(first.x, first.y, second.x, second.y, thickness, color);
+        draw_line(first.x, first.y, last.x, last.y, thickness, color);
+    }
+
+    pub fn debug_draw(&self) {
+        self.draw_outline(RED, 1.0, 0.5);
+    }
+
+    /// Pulls every vertex of a fan polygon in towards its apex (the first vertex) so that
+    /// nothing lies further than `max_distance` away, recoloring it as we go.
+    pub fn radially_clipped(&self, max_distance: f32, color: Color) -> Polygon {
+        let apex = self.vertices[0].position.xy();
+        let vertices = self
+            .vertices
+            .iter()
+            .map(|v| {
+                let p = v.position.xy();
+                let p = apex + (p - apex).clamp_length_max(max_distance);
+                Vertex::new(p.x, p.y, 0.0, 0.0, 0.0, color)
+            })
+            .collect();
+        Polygon {
+            vertices,
+            indices: self.indices.clone(),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct PatrolNode {
+    pos: Vec2,
+    facing: f32,
+    wait: Time,
+    walk: Option<Time>,
+}
+
+#[derive(Debug, Clone)]
+pub struct PatrolPath {
+    nodes: Vec<PatrolNode>,
+    curr: isize,
+    // how long the guard has been waiting at the current node, None while it walks
+    timer: Option<Time>,
+    forwards: bool,
+    full_circle: bool,
+    // overrides the speed derived from the walk timings
+    speed: Option<f32>,
+    // an index into the guard sprite maps, or a random one if None
+    skin: Option<usize>,
+    // overrides how long the guards on this path stay suspicious
+    memory: Option<f32>,
+    // has to be killed to clear a level won by killing targets
+    target: bool,
+    // tints this path's guard cones, which are plain black (untinted) if None
+    cone_color: Option<Color>,
+    // overrides how fast the guards on this path chase what they're reporting
+    report_speed: Option<f32>,
+    // the total angle a waiting guard sweeps its view across, centered on the node's facing
+    scan_arc: Option<f32>,
+    // seconds to sweep across the scan arc and back, SCAN_PERIOD if None
+    scan_period: Option<Time>,
+}
+
+impl PatrolPath {
+    // the node after the current one and which way the guard is going once it gets there. a
+    // full circle wraps around, anything else turns around at its ends. a single node is its own
+    // next node
+    fn step(&self) -> (isize, bool) {
+        let len = self.nodes.len() as isize;
+        let mut forwards = self.forwards;
+        if !self.full_circle && len > 1 {
+            if forwards && self.curr == len - 1 {
+                forwards = false;
+            } else if !forwards && self.curr == 0 {
+                forwards = true;
+            }
+        }
+        let delta = if self.full_circle || forwards { 1 } else { -1 };
+        ((self.curr + delta).rem_euclid(len), forwards)
+    }
+
+    // the node the guard walks to once it's done waiting at the current one
+    fn upcoming_node(&self) -> usize {
+        self.step().0 as usize
+    }
+
+    // moves on to the upcoming node and returns it
+    fn advance(&mut self) -> usize {
+        (self.curr, self.forwards) = self.step();
+        self.curr as usize
+    }
+}
+
+/// Adds guards to every patrol path until it has `density` times as many as were authored, or
+/// one on every node, each starting on the node furthest along the path from the others.
+fn add_density_spawns(paths: &mut Vec<PatrolPath>, density: f32) {
+    let mut groups: Vec<(Vec<Vec2>, Vec<usize>)> = Vec::new();
+    for (i, p) in paths.iter().enumerate() {
+        let nodes = p.nodes.iter().map(|n| n.pos).collect::<Vec<_>>();
+        match groups.iter_mut().find(|(n, _)| *n == nodes) {
+            Some((_, members)) => members.push(i),
+            None => groups.push((nodes, vec![i])),
+        }
+    }
+
+    for (nodes, members) in groups {
+        let target = ((members.len() as f32 * density).round() as usize).min(nodes.len());
+        let mut taken = members
+            .iter()
+            .map(|&i| paths[i].curr as usize)
+            .collect::<Vec<_>>();
+        let template = paths[members[0]].clone();
+        while taken.len() < target {
+            let gap = |a: usize, b: usize| {
+                let d = a.abs_diff(b);
+                if template.full_circle {
+                    d.min(nodes.len() - d)
+                } else {
+                    d
+                }
+            };
+            let next = (0..nodes.len())
+                .filter(|i| !taken.contains(i))
+                .max_by_key(|&i| taken.iter().map(|&t| gap(i, t)).min().unwrap())
+                .unwrap();
+            taken.push(next);
+            paths.push(PatrolPath {
+                curr: next as isize,
+                timer: None,
+                forwards: true,
+                ..template.clone()
+            });
+        }
+    }
+}
+
+#[derive(Debug)]
+enum EnemyState {
+    Dead(bool),
+    Patrolling,
+    Assessing(f32),
+    // lost sight of whatever it was assessing and is looking around where it was last seen,
+    // calming down as the suspicion drains
+    Searching(f32),
+    Reporting(bool),
+}
+
+static NEXT_ENEMY_ID: AtomicUsize = AtomicUsize::new(0);
+
+// stays valid for an enemy no matter how the enemies Vec is reordered
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct EnemyId(usize);
+
+#[derive(Debug)]
+struct Enemy {
+    id: EnemyId,
+    pos: Vec2,
+    hightlight: bool,
+    detection_radius: f32,
+    view_angle: f32,
+    facing: f32,
+    smap: SpriteMap,
+    emotes: Emotes,
+    cone: Option<(Vec2, f32, Polygon)>,
+    // a hash of the segments the cached cone was cut by, one-way windows add or remove some
+    cone_segments: u64,
+    state: EnemyState,
+    patrol_path: PatrolPath,
+    astar_path: Option<Vec<Vec2>>,
+    last_v: Vec2,
+    // the sign of the last sideways facing, which picks the sitting pose
+    last_dir_x: f32,
+    is_moving: bool,
+    walk_speed: f32,
+    // the smoothed assessment progress used to draw the red cone
+    red_cone_t: f32,
+    // the run animation plays at `run_frame_time` when moving at `run_anim_speed`, and
+    // proportionally slower or faster otherwise
+    run_frame_time: Time,
+    run_anim_speed: f32,
+    // a tracker arrow lets the player see this guard through walls until then
+    tagged_until: Time,
+    // how long a fully suspicious guard keeps searching after losing sight, before
+    // scaling by difficulty
+    memory_duration: f32,
+    // where the guard last saw something suspicious, which it walks to while it remembers
+    last_seen: Option<Vec2>,
+    // how close the player's footsteps have to be for the guard to hear them
+    hearing_radius: f32,
+    cone_color: Color,
+    report_speed: f32,
+    // how long a reporting guard has to keep sight of the player before they're caught, and how
+    // long it has so far
+    catch_delay: f32,
+    report_contact: f32,
+    // how long the guard has been waiting on its patrol, see Player::idle_time
+    idle_time: Time,
+}
+
+impl Enemy {
+    fn new(path: PatrolPath, sprite: SpriteMap, emotes: Emotes) -> Self {
+        let walk_speed = path.speed.unwrap_or(50.0);
+        let memory_duration = path.memory.unwrap_or(2.0);
+        let cone_color = path.cone_color.unwrap_or(BLACK);
+        let report_speed = path.report_speed.unwrap_or(125.0);
+        Self {
+            id: EnemyId(NEXT_ENEMY_ID.fetch_add(1, Ordering::Relaxed)),
+            pos: path.nodes[path.curr as usize].pos,
+            hightlight: false,
+            detection_radius: 512.0,
+            facing: path.nodes[path.curr as usize].facing,
+            view_angle: 90.0f32.to_radians(),
+            cone: None,
+            cone_segments: 0,
+            state: EnemyState::Patrolling,
+            patrol_path: path,
+            astar_path: None,
+            smap: sprite,
+            emotes,
+            last_v: vec2(0.0, 0.0),
+            last_dir_x: 1.0,
+            is_moving: false,
+            walk_speed,
+            memory_duration,
+            last_seen: None,
+            hearing_radius: HEARING_RADIUS,
+            red_cone_t: 0.0,
+            run_frame_time: 0.1,
+            run_anim_speed: 100.0,
+            tagged_until: 0.0,
+            cone_color,
+            report_speed,
+            catch_delay: 0.3,
+            report_contact: 0.0,
+            idle_time: 0.0,
+        }
+    }
+
+    fn dead(&self) -> bool {
+        if let EnemyState::Dead(_) = self.state {
+            true
+        } else {
+            false
+        }
+    }
+
+    fn die(&mut self) {
+        match self.state {
+            EnemyState::Dead(_) => {}
+            _ => {
+                self.state = EnemyState::Dead(false);
+                emit(GameEvent::EnemyKilled(self.id));
+            }
+        }
+    }
+
+    fn curr_sprite(&self) -> &Sprite {
+        let mut v = rotate_vec2(vec2(1.0, 0.0), -self.facing);
+        if v.x.abs() > v.y.abs() {
+            v.y = 0.0;
+        } else {
+            v.x = 0.0;
+        }
+        match self.state {
+            EnemyState::Reporting(_) if !self.is_moving => self.smap.get("use_phone"),
+            EnemyState::Dead(true) => self.smap.get(sit_name(self.last_dir_x)),
+            EnemyState::Dead(false) => self.smap.get(vel_to_name("damage", v)),
+            _ => {
+                if self.is_moving {
+                    self.smap.get(vel_to_name("run", v))
+                } else if self.idle_time >= IDLE_LOOK_AROUND_TIME {
+                    self.smap.get(vel_to_name("look_around", v))
+                } else {
+                    self.smap.get(vel_to_name("idle", v))
+                }
+            }
+        }
+    }
+
+    fn is_point_in_full_cone(&self, p: Vec2) -> bool {
+        is_point_in_view(
+            self.pos,
+            self.facing,
+            self.view_angle,
+            self.detection_radius,
+            p,
+        )
+    }
+
+    fn generate_cone(&self, segments: &[(Vec2, Vec2)], color: Color, max_distance: f32) -> Polygon {
+        generate_view_cone(
+            self.pos,
+            self.facing,
+            self.view_angle,
+            segments,
+            color,
+            max_distance,
+        )
+    }
+
+    fn get_suspicion(
+        &mut self,
+        shadow_segments: &[(Vec2, Vec2)],
+        dead_enemy_rects: &[Rect],
+        player: &Player,
+    ) -> Option<(Vec2, bool)> {
+        if self.dead() {
+            return None;
+        }
+        // guards that are standing still or only drifting a little keep the cone they had
+        let segments_hash = hash_segments(shadow_segments);
+        let should_regen = match self.cone.as_ref() {
+            Some(&(pos, facing, _)) => {
+                let turned = (self.facing - facing).normalized_rads();
+                self.pos.distance(pos) > CONE_REUSE_DISTANCE
+                    || turned.min(TAU - turned) > CONE_REUSE_ANGLE
+                    || segments_hash != self.cone_segments
+            }
+            None => true,
+        };
+        if should_regen {
+            self.cone_segments = segments_hash;
+            self.cone = Some((
+                self.pos,
+                self.facing,
+                self.generate_cone(shadow_segments, self.cone_color, self.detection_radius),
+            ));
+        }
+
+        for (idx, r) in [player.detection_rect()]
+            .iter()
+            .chain(dead_enemy_rects.iter())
+            .enumerate()
+        {
+            if self.is_rect_in_cone(*r) {
+                return Some((r.center(), idx == 0));
+            }
+        }
+        None
+    }
+
+    // whether the player's footsteps are loud enough to reach the guard, and not muffled by a
+    // wall in between
+    fn hears(&self, player: &Player, shadow_segments: &[(Vec2, Vec2)]) -> bool {
+        match player.noise() {
+            Some(noise) => {
+                (player.pos - self.pos).length() <= self.hearing_radius * noise
+                    && !shadow_segments
+                        .iter()
+                        .any(|&(a, b)| intersect_lines(self.pos, player.pos, a, b).is_some())
+            }
+            None => false,
+        }
+    }
+
+    // uses the cone cached by the last call to `detection`
+    fn is_rect_in_cone(&self, r: Rect) -> bool {
+        if self.dead() || self.cone.is_none() {
+            return false;
+        }
+        let cone = &self.cone.as_ref().unwrap().2;
+        let points = [
+            vec2(r.x, r.y),
+            vec2(r.x + r.w, r.y),
+            vec2(r.x, r.y + r.h),
+            vec2(r.x + r.w, r.y + r.h),
+        ];
+        if !points.iter().any(|p| self.is_point_in_full_cone(*p)) {
+            return false;
+        }
+        points.into_iter().any(|p| cone.contains(p))
+    }
+
+    fn tick(
+        &mut self,
+        pathfinder: &Pathfinder,
+        colliders: &[Rect],
+        shadow_segments: &[(Vec2, Vec2)],
+        dead_enemy_rects: &[Rect],
+        player: &mut Player,
+        dt: f32,
+    ) {
+        self.is_moving = false;
+
+        // self.goto(
+        //     pathfinder
+        //         .get_path(self.collision_rect(), player.pos)
+        //         .map(|p| *p.get(1).unwrap_or(&self.pos))
+        //         .unwrap_or(self.pos),
+        //     colliders,
+        // );
+        // return;
+
+        let suspicion = self.get_suspicion(shadow_segments, dead_enemy_rects, player);
+        let heard = self.hears(player, shadow_segments);
+        match self.state {
+            EnemyState::Patrolling if suspicion.is_some() => {
+                play("alert", 1.0, false);
+                play_at("guard_suspicious", 1.0, self.pos, player.pos);
+                self.state = EnemyState::Assessing(0.0);
+            }
+            // hearing alone doesn't give the guard anything to assess, so it goes to look
+            EnemyState::Patrolling if heard => {
+                play("alert", 1.0, false);
+                play_at("guard_suspicious", 1.0, self.pos, player.pos);
+                self.last_seen = Some(player.pos);
+                self.state = EnemyState::Searching(HEARD_SUSPICION);
+            }
+            EnemyState::Patrolling => {
+                let curr = self.patrol_path.curr as usize;
+                let reached_goal = {
+                    if let Some(astar) = self.astar_path.as_ref() {
+                        astar.len() == 0
+                    } else {
+                        (self.pos - self.patrol_path.nodes[curr].pos).length().abs() < 16.0
+                    }
+                };
+                if !reached_goal {
+                    if let Some(path) = self.astar_path.as_mut() {
+                        if debug() {
+                            for &p in path.iter() {
+                                debug_draw(move || {
+                                    draw_circle(p.x, p.y, 4.0, RED);
+                                });
+                            }
+                        }
+                        while let Some(first) = path.first() {
+                            if *first == self.pos {
+                                path.remove(0);
+                            } else {
+                                break;
+                            }
+                        }
+                        if let Some(&target) = path.first() {
+                            self.goto(target, colliders, dt);
+                        }
+                    } else {
+                        let target = self.patrol_path.nodes[curr].pos;
+                        self.goto(target, colliders, dt);
+                    }
+                } else {
+                    let timer = match self.patrol_path.timer.as_mut() {
+                        Some(timer) => {
+                            *timer += dt as Time;
+                            *timer
+                        }
+                        None => *self.patrol_path.timer.insert(0.0),
+                    };
+                    self.astar_path = None;
+                    self.facing = self.patrol_path.nodes[curr].facing;
+                    if let Some(arc) = self.patrol_path.scan_arc {
+                        let period = self.patrol_path.scan_period.unwrap_or(SCAN_PERIOD);
+                        let phase = timer / period * 2.0 * std::f64::consts::PI;
+                        self.facing += phase.sin() as f32 * arc / 2.0;
+                    }
+                    if timer > self.patrol_path.nodes[curr].wait {
+                        let next_idx = self.patrol_path.advance();
+                        if next_idx != curr {
+                            self.walk_speed = match self.patrol_path.speed {
+                                Some(speed) => speed,
+                                None => {
+                                    let walk_time = if self.patrol_path.forwards {
+                                        self.patrol_path.nodes[curr].walk.unwrap()
+                                    } else {
+                                        self.patrol_path.nodes[next_idx].walk.unwrap()
+                                    };
+                                    (self.patrol_path.nodes[next_idx].pos - self.pos).length()
+                                        / walk_time as f32
+                                }
+                            };
+                            self.astar_path = pathfinder.get_path(
+                                self.collision_rect(),
+                                self.patrol_path.nodes[next_idx].pos,
+                            );
+                            self.patrol_path.timer = None;
+                        }
+                    }
+                }
+            }
+            EnemyState::Assessing(t) | EnemyState::Searching(t) => {
+                // XXX
+                // self.face_towards_player(pos);
+                let reset_duration = self.memory_duration * settings().difficulty.memory_scale();
+
+                if let Some((sus_pos, is_player)) = suspicion {
+                    self.last_seen = Some(sus_pos);
+                    let dist = (sus_pos - self.pos).length();
+                    match assess_step(t, dist, self.detection_radius, dt) {
+                        Some(t) => self.state = EnemyState::Assessing(t),
+                        None => {
+                            play_at("guard_intruder", 1.0, self.pos, player.pos);
+                            self.state = EnemyState::Reporting(is_player);
+                        }
+                    }
+                } else {
+                    if heard {
+                        self.last_seen = Some(player.pos);
+                    }
+                    let t = t - dt / reset_duration;
+                    if t <= 0.0 {
+                        play_at("guard_nothing", 1.0, self.pos, player.pos);
+                        self.state = EnemyState::Patrolling;
+                        self.last_seen = None;
+                        // slipping away only counts once the guard has given up looking
+                        self.report_contact = 0.0;
+                        // head back to where the patrol was interrupted
+                        self.astar_path = pathfinder.get_path(
+                            self.collision_rect(),
+                            self.patrol_path.nodes[self.patrol_path.curr as usize].pos,
+                        );
+                        self.patrol_path.timer = None;
+                    } else {
+                        if let Some(target) = self.last_seen {
+                            if (target - self.pos).length() > 8.0 {
+                                self.goto(target, colliders, dt);
+                            }
+                        }
+                        self.state = EnemyState::Searching(t);
+                    }
+                }
+            }
+            EnemyState::Reporting(is_player) => match suspicion {
+                Some((sus_pos, _)) => {
+                    self.last_seen = Some(sus_pos);
+                    self.report_contact += dt;
+                    if self.report_contact >= self.catch_delay {
+                        player.caught(!is_player, self.id);
+                    } else if (sus_pos - self.pos).length() > 8.0 {
+                        self.goto(sus_pos, colliders, dt);
+                    }
+                }
+                // breaking line of sight in time only leaves the guard fully suspicious
+                None if self.report_contact < self.catch_delay => {
+                    self.state = EnemyState::Searching(1.0);
+                }
+                None => player.caught(!is_player, self.id),
+            },
+            EnemyState::Dead(has_played_death_anim) => {
+                if !has_played_death_anim && self.curr_sprite().about_to_loop() {
+                    self.state = EnemyState::Dead(true);
+                }
+            }
+        }
+
+        let red_cone_target = match self.state {
+            EnemyState::Assessing(t) | EnemyState::Searching(t) => t,
+            _ => 0.0,
+        };
+        let red_cone_speed = 10.0;
+        self.red_cone_t += (red_cone_target - self.red_cone_t) * (dt * red_cone_speed).min(1.0);
+
+        let dir_x = rotate_vec2(vec2(1.0, 0.0), -self.facing).x;
+        if dir_x.abs() > 0.01 {
+            self.last_dir_x = dir_x.signum();
+        }
+
+        // purely cosmetic, the cone keeps facing the same way while the guard looks around
+        if !matches!(self.state, EnemyState::Patrolling) || self.is_moving {
+            self.idle_time = 0.0;
+        } else if self.idle_time >= IDLE_LOOK_AROUND_TIME {
+            if self.curr_sprite().about_to_loop() {
+                self.idle_time = 0.0;
+            }
+        } else {
+            self.idle_time += dt as Time;
+        }
+
+        {
+            let px = self.pos.x;
+            let py = self.pos.y;
+            let facing = self.pos + rotate_vec2(vec2(1.0, 0.0), -self.facing) * 15.0;
+            debug_draw(move || {
+                draw_circle(px, py, 1.0, GREEN);
+                draw_circle(facing.x, facing.y, 1.0, RED);
+            })
+        }
+    }
+
+    fn speed(&self) -> f32 {
+        match self.state {
+            EnemyState::Reporting(_) => self.report_speed,
+            _ => self.walk_speed,
+        }
+    }
+
+    fn goto(&mut self, target: Vec2, colliders: &[Rect], dt: f32) {
+        let dir = target - self.pos;
+        self.facing = dir
+            .normalize_or(vec2(1.0, 0.0))
+            .angle_between(vec2(1.0, 0.0))
+            .normalized_rads();
+        let dist = dir.length();
+        let v = dir.normalize_or_zero() * (self.speed() * dt).min(dist);
+        self.is_moving = true;
+        self.last_v = v;
+        let v = slide_rect(self.collision_rect(), v, colliders);
+        self.pos += v;
+    }
+
+    fn draw_cone_outline(&self) {
+        match self.state {
+            EnemyState::Dead(_) => return,
+            _ => {}
+        }
+        if let Some((_, _, cone)) = self.cone.as_ref() {
+            cone.draw_eye(MY_RED, 1.0, 48.0);
+        }
+    }
+
+    fn draw_cone(&self) {
+        match self.state {
+            EnemyState::Dead(_) => return,
+            _ => {}
+        }
+        if let Some((_, _, cone)) = self.cone.as_ref() {
+            draw_custom_shape(&cone.vertices, &cone.indices);
+        }
+    }
+
+    fn draw_red_cone(&self) {
+        match self.state {
+            EnemyState::Dead(_) => return,
+            _ => {}
+        }
+        if let (EnemyState::Assessing(_) | EnemyState::Searching(_), Some((_, _, cone))) =
+            (&self.state, self.cone.as_ref())
+        {
+            if self.red_cone_t > 0.01 {
+                let small_cone = cone.radially_clipped(
+                    self.detection_radius * self.red_cone_t,
+                    Color::from_rgba(255, 0, 0, 255),
+                );
+                draw_custom_shape(&small_cone.vertices, &small_cone.indices);
+            }
+        }
+    }
+
+    fn draw_tag_outline(&self) {
+        let remaining = self.tagged_until - get_time();
+        if self.dead() || remaining <= 0.0 {
+            return;
+        }
+        // fade out over the last second of the tag
+        let color = Color {
+            a: remaining.min(1.0) as f32,
+            ..YELLOW
+        };
+        let r = self.rect();
+        draw_rectangle_lines(r.x, r.y, r.w, r.h, 1.0, color);
+        if let Some((_, _, cone)) = self.cone.as_ref() {
+            cone.draw_eye(color, 1.0, 48.0);
+        }
+    }
+
+    // points out the guard that caught the player, so they can see what gave them away
+    fn draw_culprit_marker(&self) {
+        let r = self.rect();
+        draw_rectangle_lines(r.x, r.y, r.w, r.h, 1.0, MY_RED);
+        if let Some((_, _, cone)) = self.cone.as_ref() {
+            cone.draw_eye(MY_RED, 1.0, 48.0);
+        }
+        // bobbing above the guard's head
+        let bob = (get_time() * 6.0).sin() as f32 * 2.0;
+        draw_chevron(vec2(r.center().x, r.y - 4.0 + bob), vec2(0.0, 1.0), MY_RED);
+    }
+
+    fn rect(&self) -> Rect {
+        let size = self.curr_sprite().size();
+        Rect::new(
+            self.pos.x - size.x / 2.0,
+            self.pos.y - size.y / 2.0,
+            size.x,
+            size.y,
+        )
+    }
+
+    fn collision_rect(&self) -> Rect {
+        collision_rect(self.rect())
+    }
+
+    fn detection_rect(&self) -> Rect {
+        detection_rect(self.rect())
+    }
+
+    fn draw_emotes(&self) {
+        let size = self.curr_sprite().size();
+        let mut sprite_top_left = self.pos - size / 2.0;
+        sprite_top_left.y -= SPRITE_Y_OFFSET;
+        let top_left = self.emotes.anchor(sprite_top_left, size);
+        match self.state {
+            EnemyState::Assessing(_) => {
+                self.emotes.draw(top_left, "question");
+            }
+            EnemyState::Searching(_) => {
+                self.emotes.draw_ellipsis(top_left);
+            }
+            EnemyState::Reporting(_) => {
+                self.emotes.draw(top_left, "exclamation");
+            }
+            _ => {
+                self.emotes.reset();
+            }
+        }
+    }
+
+    fn draw_facing_marker(&self) {
+        if self.dead() {
+            return;
+        }
+        let dir = rotate_vec2(vec2(1.0, 0.0), -self.facing);
+        draw_chevron(self.pos + dir * 18.0, dir, MY_WHITE);
+    }
+
+    // the way a waiting guard will head off in, for the last moments before it does
+    fn upcoming_turn(&self) -> Option<Vec2> {
+        if !matches!(self.state, EnemyState::Patrolling) {
+            return None;
+        }
+        let timer = self.patrol_path.timer?;
+        let curr = self.patrol_path.curr as usize;
+        let remaining = self.patrol_path.nodes[curr].wait - timer;
+        if !(0.0..=TURN_TELEGRAPH_TIME).contains(&remaining) {
+            return None;
+        }
+        let next = self.patrol_path.upcoming_node();
+        // a single node sentry never moves on
+        if next == curr {
+            return None;
+        }
+        (self.patrol_path.nodes[next].pos - self.pos).try_normalize()
+    }
+
+    // a chevron that fades in towards where the guard is about to turn
+    fn draw_turn_telegraph(&self) {
+        if let Some(dir) = self.upcoming_turn() {
+            let timer = self.patrol_path.timer.unwrap();
+            let wait = self.patrol_path.nodes[self.patrol_path.curr as usize].wait;
+            let t = 1.0 - (wait - timer) / TURN_TELEGRAPH_TIME;
+            let color = Color::new(MY_RED.r, MY_RED.g, MY_RED.b, t as f32);
+            draw_chevron(self.pos + dir * 22.0, dir, color);
+        }
+    }
+
+    // a faint line through the patrol nodes with how long the guard waits at each, for practice
+    fn draw_patrol_route(&self) {
+        if self.dead() {
+            return;
+        }
+        let color = Color::new(MY_WHITE.r, MY_WHITE.g, MY_WHITE.b, 0.35);
+        let nodes = &self.patrol_path.nodes;
+        let mut pairs = (1..nodes.len()).map(|i| (i - 1, i)).collect::<Vec<_>>();
+        if self.patrol_path.full_circle && nodes.len() > 2 {
+            pairs.push((nodes.len() - 1, 0));
+        }
+        for (a, b) in pairs {
+            let (a, b) = (nodes[a].pos, nodes[b].pos);
+            draw_line(a.x, a.y, b.x, b.y, 1.0, color);
+        }
+        for node in nodes.iter() {
+            draw_circle(node.pos.x, node.pos.y, 2.0, color);
+            if node.wait > 0.0 {
+                draw_text(
+                    &format!("{:.1}s", node.wait),
+                    node.pos.x + 4.0,
+                    node.pos.y - 4.0,
+                    12.0,
+                    color,
+                );
+            }
+        }
+    }
+
+    // guards that spawn inside (merged) geometry are nudged out to the closest cell they fit in
+    fn move_to_first_node(&mut self, pathfinder: &Pathfinder) {
+        let rect = self.collision_rect();
+        if !pathfinder.is_rect_colliding(rect) {
+            return;
+        }
+        let search_radius = 8;
+        if let Some(pos) = pathfinder.nearest_walkable(self.pos, rect.size(), search_radius) {
+            self.pos = pos;
+        }
+    }
+
+    // consecutive patrol nodes that the guard has no way to walk between
+    fn validate_patrol_path(&self, pathfinder: &Pathfinder) -> Vec<String> {
+        let nodes = &self.patrol_path.nodes;
+        let mut pairs = (1..nodes.len()).map(|i| (i - 1, i)).collect::<Vec<_>>();
+        if self.patrol_path.full_circle && nodes.len() > 2 {
+            pairs.push((nodes.len() - 1, 0));
+        }
+
+        let size = self.collision_rect().size();
+        pairs
+            .into_iter()
+            .filter(|&(from, to)| {
+                let pos = nodes[from].pos;
+                let rect = Rect::new(pos.x - size.x / 2.0, pos.y - size.y / 2.0, size.x, size.y);
+                pathfinder.is_direct_path_blocked(rect, nodes[to].pos)
+                    && pathfinder.get_path(rect, nodes[to].pos).is_none()
+            })
+            .map(|(from, to)| format!("patrol nodes {from} and {to} are not connected by any path"))
+            .collect()
+    }
+}
+
+impl Draw for Enemy {
+    fn draw(&self) {
+        if self.is_moving {
+            let speed_ratio = (self.run_anim_speed / self.speed()).clamp(0.5, 3.0);
+            self.curr_sprite()
+                .set_frame_time(self.run_frame_time * speed_ratio as Time);
+        }
+
+        draw_ground_shadow(self.collision_rect(), 0.0);
+
+        let mut top_left = self.pos - self.curr_sprite().size() / 2.0;
+        top_left.y -= SPRITE_Y_OFFSET;
+        self.curr_sprite().draw(top_left);
+
+        {
+            let r = self.rect();
+            let t = self.collision_rect();
+            debug_draw(move || {
+                draw_rectangle_lines(r.x, r.y, r.w, r.h, 1.0, RED);
+                draw_rectangle_lines(t.x, t.y, t.w, t.h, 1.0, BLUE);
+            });
+        }
+
+        // if let Some(cone) = self.cone.as_ref() {
+        //     cone.draw_eye(MY_WHITE, 1.0, 64.0);
+        // }
+    }
+
+    fn sort_order(&self) -> f32 {
+        self.pos.y + self.curr_sprite().size().y / 2.0
+    }
+}
+
+// the sheets only have sitting frames facing left and right, so facing straight up or down
+// keeps whichever side was faced last
+fn sit_name(dir_x: f32) -> &'static str {
+    if dir_x < 0.0 {
+        "sit_left"
+    } else {
+        "sit_right"
+    }
+}
+
+fn vel_to_name(name: &str, v: Vec2) -> String {
+    if v.y < 0.0 {
+        format!("{}_up", name)
+    } else if v.y > 0.0 {
+        format!("{}_down", name)
+    } else if v.x < 0.0 {
+        format!("{}_left", name)
+    } else {
+        format!("{}_right", name)
+    }
+}
+
+#[derive(Debug, Clone)]
+struct Intersection {
+    pos: Vec2,
+    normal: Vec2,
+    entity: IsectType,
+    new_dir: Vec2,
+}
+
+// indices into the scene's enemies and colliders
+#[derive(Debug, Clone, Copy)]
+enum IsectType {
+    Enemy(usize),
+    Collider(usize),
+    Air,
+}
+
+impl IsectType {
+    fn rect(&self, enemies: &[Enemy], colliders: &[Rect]) -> Rect {
+        match self {
+            Self::Enemy(e) => enemies[*e].rect(),
+            Self::Collider(c) => colliders[*c],
+            _ => Rect::default(),
+        }
+    }
+}
+
+// cells of the quadtrees over the level's walls
+const QUADTREE_CELL: Vec2 = vec2(64.0, 64.0);
+
+// a level's colliders, along with a quadtree over their edges so a ray only has to be tested
+// against the colliders it passes near
+struct Colliders {
+    rects: Vec<Rect>,
+    edges: QuadTree,
+}
+
+impl Colliders {
+    fn new(rects: Vec<Rect>) -> Self {
+        let edges = QuadTree::build_around(
+            QUADTREE_CELL,
+            &rects_to_segments(&rects).collect::<Vec<_>>(),
+        );
+        Self { rects, edges }
+    }
+
+    // the index of every collider the segment might cross, lowest first like the colliders
+    // themselves. rects_to_segments gives each collider four edges in order
+    fn along(&self, start: Vec2, end: Vec2) -> Vec<usize> {
+        let mut ids = self
+            .edges
+            .ids_by_segment((start, end))
+            .into_iter()
+            .map(|edge| edge / 4)
+            .collect::<Vec<_>>();
+        ids.dedup();
+        ids
+    }
+}
+
+impl Deref for Colliders {
+    type Target = [Rect];
+
+    fn deref(&self) -> &[Rect] {
+        &self.rects
+    }
+}
+
+fn get_intersections(
+    mut start: Vec2,
+    mut dir: Vec2,
+    enemies: &[Enemy],
+    colliders: &Colliders,
+) -> Vec<Intersection> {
+    let mut all: Vec<Intersection> = Vec::new();
+    let max_bounces = 4;
+    let mut hit_enemies = Vec::new();
+    let mut have_bounced = false;
+    for _ in 0..max_bounces {
+        let endpoint = start + dir * 1000.0;
+        let mut closest_int = (endpoint, Vec2::ZERO, endpoint, Vec2::ZERO, IsectType::Air);
+        let nearby = colliders.along(start, endpoint);
+        for e in (0..enemies.len())
+            .map(IsectType::Enemy)
+            .chain(nearby.iter().copied().map(IsectType::Collider))
+        {
+            if let IsectType::Enemy(idx) = e {
+                if enemies[idx].dead() || hit_enemies.contains(&idx) {
+                    continue;
+                }
+            }
+            let rect = e.rect(enemies, colliders);
+            if let Some((pb, nb, pe, ne)) = line_rect_intersect(start, endpoint, rect) {
+                if (pb - start).length() < (closest_int.0 - start).length() {
+                    closest_int = (pb, nb, pe, ne, e);
+                }
+            }
+        }
+
+        // two colliders meeting at the hit point (e.g. an inside corner) both get a say in the normal
+        if let IsectType::Collider(_) = closest_int.4 {
+            let normals = nearby
+                .iter()
+                .filter_map(|&c| line_rect_intersect(start, endpoint, colliders[c]))
+                .filter(|(pb, ..)| (*pb - closest_int.0).length() < CORNER_EPSILON)
+                .map(|(_, nb, ..)| nb)
+                .collect::<Vec<_>>();
+            if normals.len() > 1 {
+                closest_int.1 = corner_normal(dir, &normals);
+            }
+        }
+
+        match closest_int {
+            (_, _, pe, _, IsectType::Enemy(idx)) => {
+                hit_enemies.push(idx);
+                start = pe + dir * 1.0;
+            }
+            (pb, nb, _, _, IsectType::Collider(_)) => {
+                let from = (start - pb).normalize_or(-dir);
+                let angle = 2.0 * from.angle_between(nb);
+                let rotator = vec2(angle.cos(), angle.sin());
+                dir = from.rotate(rotator);
+                start = pb + dir * 1.0;
+            }
+            (pb, _, _, _, IsectType::Air) => {
+                start = pb;
+            }
+        }
+        let int = Intersection {
+            pos: closest_int.0,
+            normal: closest_int.1,
+            entity: closest_int.4,
+            new_dir: dir,
+        };
+
+        all.push(int);
+        match closest_int.4 {
+            IsectType::Air => break,
+            IsectType::Enemy(_) => break,
+            IsectType::Collider(_) if have_bounced => break,
+            IsectType::Collider(_) => have_bounced = true,
+        }
+    }
+    all
+}
+
+// the segments that block the sight of a guard at `pos`, which are `segments` plus any one-way
+// window it isn't on the see-through side of
+fn segments_seen_from<'a>(
+    pos: Vec2,
+    segments: &'a [(Vec2, Vec2)],
+    windows: &[OneWayWindow],
+) -> Cow<'a, [(Vec2, Vec2)]> {
+    if windows.is_empty() {
+        return Cow::Borrowed(segments);
+    }
+    let blocking = windows
+        .iter()
+        .filter(|w| !w.is_see_through_from(pos))
+        .map(|w| w.rect)
+        .collect::<Vec<_>>();
+    let mut all = segments.to_vec();
+    all.extend(rects_to_segments(&blocking));
+    Cow::Owned(all)
+}
+
+// how much of the cursor's distance from the screen center the camera leans while aiming, at
+// most AIM_PAN_MAX world units, easing in and out at AIM_PAN_SPEED
+const AIM_PAN_FRACTION: f32 = 0.35;
+const AIM_PAN_MAX: f32 = 96.0;
+const AIM_PAN_SPEED: f32 = 6.0;
+
+// keeps dashed lines the same size on screen whatever the zoom, resolution or dpi, relative to
+// how they were tuned at 512 world units over 1080 pixels
+fn dash_scale(camera: &Camera2D) -> f32 {
+    let world_height = 2.0 / camera.zoom.y.abs();
+    (world_height / screen_height()) / (512.0 / 1080.0)
+}
+
+fn draw_chevron(tip: Vec2, dir: Vec2, color: Color) {
+    for angle in [-0.6, 0.6] {
+        let wing = tip - rotate_vec2(dir, angle) * 6.0;
+        draw_line(tip.x, tip.y, wing.x, wing.y, 1.5, color);
+    }
+}
+
+fn rects_to_segments<'a>(rects: &'a [Rect]) -> impl Iterator<Item = (Vec2, Vec2)> + 'a {
+    rects
+        .iter()
+        .map(|&Rect { x, y, w, h }| {
+            [
+                (vec2(x, y), vec2(x + w, y)),
+                (vec2(x + w, y), vec2(x + w, y + h)),
+                (vec2(x, y), vec2(x, y + h)),
+                (vec2(x, y + h), vec2(x + w, y + h)),
+            ]
+        })
+        .flatten()
+}
+
+fn is_point_in_view(pos: Vec2, facing: f32, view_angle: f32, max_distance: f32, p: Vec2) -> bool {
+    if (p - pos).length() > max_distance {
+        return false;
+    }
+    let angle = (p - pos)
+        .normalize()
+        .angle_between(vec2(1.0, 0.0))
+        .normalized_rads();
+    let max = (facing + view_angle / 2.0).normalized_rads();
+    let min = (facing - view_angle / 2.0).normalized_rads();
+
+    if max > min && angle < max && angle > min {
+        true
+    } else if min > max && (angle > min || angle < max) {
+        true
+    } else {
+        false
+    }
+}
+
+// how far a guard can move or turn before its cached cone is thrown away
+const CONE_REUSE_DISTANCE: f32 = 0.5;
+const CONE_REUSE_ANGLE: f32 = 0.005;
+
+// far cheaper than casting the cone again, and unlike a count it notices one segment swapped for
+// another, as when the guard walks from one side of a one-way window to the other
+fn hash_segments(segments: &[(Vec2, Vec2)]) -> u64 {
+    let mut hasher = DefaultHasher::new();
+    for (a, b) in segments {
+        [a.x, a.y, b.x, b.y].map(f32::to_bits).hash(&mut hasher);
+    }
+    hasher.finish()
+}
+
+fn generate_view_cone(
+    pos: Vec2,
+    facing: f32,
+    view_angle: f32,
+    segments: &[(Vec2, Vec2)],
+    color: Color,
+    max_distance: f32,
+) -> Polygon {
+    // no ray reaches past max_distance, so walls further away than that can't cut the cone
+    let segments = segments
+        .iter()
+        .filter(|(a, b)| segment_distance(pos, *a, *b) <= max_distance)
+        .copied()
+        .collect::<Vec<_>>();
+    let mut directions = Vec::new();
+    let degree_step = 2;
+    let mut max = 0.0;
+    for d in (0..(view_angle.to_degrees()) as i32).step_by(degree_step) {
+        let angle = (facing + view_angle / 2.0 - (d as f32).to_radians()).normalized_rads();
+        if d == 0 {
+            max = angle;
+        }
+        directions.push(angle);
+    }
+    let min = *directions.last().unwrap();
+    for p in segments
+        .iter()
+        .map(|(a, b)| [*a, *b])
+        .flatten()
+        .filter(|p| is_point_in_view(pos, facing, view_angle, max_distance, *p))
+    {
+        let angle = (p - pos)
+            .normalize()
+            .angle_between(vec2(1.0, 0.0))
+            .normalized_rads();
+        if (max > min && angle < max && angle > min) || (min > max && (angle > min || angle < max))
+        {
+            directions.push(angle);
+        }
+    }
+    generate_shadow_polygon(
+        pos,
+        &segments,
+        directions,
+        max_distance,
+        false,
+        color,
+        Some(-facing - view_angle / 2.0),
+    )
+}
+
+fn generate_shadow_polygon(
+    pos: Vec2,
+    segments: &[(Vec2, Vec2)],
+    directions: impl IntoIterator<Item = f32>,
+    max_distance: f32,
+    full_circle: bool,
+    color: Color,
+    start_from: Option<f32>,
+) -> Polygon {
+    let start_from = rotate_vec2(vec2(1.0, 0.0), start_from.unwrap_or(0.0) - TAU / 32.0);
+    let mut ints = Vec::new();
+    for angle in directions {
+        let mut add_int = |dir: Vec2| {
+            let endpoint = pos + dir * max_distance;
+            let mut closest_int = endpoint;
+            for ls in segments.iter() {
+                if let Some(int) = intersect_lines(pos, endpoint, ls.0, ls.1) {
+                    if int == pos {
+                        continue;
+                    }
+                    if (closest_int - pos).length() > (int - pos).length() {
+                        closest_int = int;
+                    }
+                }
+            }
+            ints.push(closest_int);
+        };
+        let dir = rotate_vec2(vec2(1.0, 0.0), -angle).normalize();
+        add_int(dir);
+        add_int(rotate_vec2(dir, 0.0001));
+        add_int(rotate_vec2(dir, -0.0001));
+    }
+
+    let angle = |p: Vec2| {
+        -(p - pos)
+            .normalize()
+            .angle_between(start_from)
+            .normalized_rads()
+    };
+    let radius = |p: Vec2| (p - pos).length();
+    ints.sort_by(|a, b| angle(*a).partial_cmp(&angle(*b)).unwrap());
+
+    // points at (nearly) the same angle are where the boundary jumps between a near and a far
+    // wall, and they have to go in the order the boundary actually visits them, otherwise the fan
+    // folds a sliver triangle back over itself
+    let angle_eps = 0.00001;
+    let mut run_start = 0;
+    while run_start < ints.len() {
+        let mut run_end = run_start + 1;
+        while run_end < ints.len() && angle(ints[run_end]) - angle(ints[run_start]) < angle_eps {
+            run_end += 1;
+        }
+        if run_end - run_start > 1 {
+            // continue from whichever end of the run is closest to the point before it, or lead
+            // into the point after it for a run at the very start
+            let (neighbour, towards_start) = if run_start > 0 {
+                (Some(ints[run_start - 1]), true)
+            } else {
+                (ints.get(run_end).copied(), false)
+            };
+            let run = &mut ints[run_start..run_end];
+            run.sort_by(|a, b| radius(*a).partial_cmp(&radius(*b)).unwrap());
+            if let Some(n) = neighbour {
+                let first_gap = (radius(run[0]) - radius(n)).abs();
+                let last_gap = (radius(run[run.len() - 1]) - radius(n)).abs();
+                if (last_gap < first_gap) == towards_start {
+                    run.reverse();
+                }
+            }
+        }
+        run_start = run_end;
+    }
+
+    let mut vertices = Vec::new();
+    let mut indices = Vec::<u16>::new();
+    vertices.push(Vertex::new(pos.x, pos.y, 0.0, 0.0, 0.0, color));
+
+    for i in 0..ints.len() {
+        let x = ints[i].x;
+        let y = ints[i].y;
+        let len = ints.len();
+        debug_draw(move || {
+            draw_circle(
+                x,
+                y,
+                2.0,
+                Color {
+                    r: i as f32 / len as f32,
+                    ..BLUE
+                },
+            );
+        });
+        let p = ints[i];
+        vertices.push(Vertex::new(p.x, p.y, 0.0, 0.0, 0.0, color));
+        if i < ints.len() - 1 {
+            let p = ints[i + 1];
+            vertices.push(Vertex::new(p.x, p.y, 0.0, 0.0, 0.0, color));
+            indices.extend_from_slice(&[
+                0,
+                (vertices.len() - 2) as u16,
+                (vertices.len() - 1) as u16,
+            ]);
+        } else if full_circle {
+            indices.extend_from_slice(&[0, (vertices.len() - 1) as u16, 1]);
+        }
+    }
+
+    Polygon { vertices, indices }
+}
+
+pub struct Light {
+    pos: Vec2,
+    radius: f32,
+    color: Color,
+}
+
+// how bright the parts of a lit level that no light reaches still are
+const LIGHT_FLOOR: f32 = 0.3;
+
+impl Draw for Light {
+    // a radial gradient fading from the light's colour at the center to nothing at its radius
+    fn draw(&self) {
+        let segments = 32;
+        let edge = Color {
+            a: 0.0,
+            ..self.color
+        };
+        let mut vertices = vec![Vertex::new(
+            self.pos.x, self.pos.y, 0.0, 0.0, 0.0, self.color,
+        )];
+        let mut indices = Vec::<u16>::new();
+        for i in 0..segments {
+            let angle = i as f32 / segments as f32 * 2.0 * std::f32::consts::PI;
+            let p = self.pos + vec2(angle.cos(), angle.sin()) * self.radius;
+            vertices.push(Vertex::new(p.x, p.y, 0.0, 0.0, 0.0, edge));
+            indices.extend_from_slice(&[0, i + 1, (i + 1) % segments + 1]);
+        }
+        draw_custom_shape(&vertices, &indices);
+    }
+
+    fn sort_order(&self) -> f32 {
+        0.0
+    }
+}
+
+static mut ROTATING: bool = true;
+
+lazy_static! {
+    static ref DEBUG_DRAWER: Mutex<DebugDrawer> = Mutex::new(DebugDrawer::new());
+    static ref DEBUG: AtomicBool = AtomicBool::new(false);
+}
+
+fn debug_draw(f: impl Fn() + Send + Sync + 'static) {
+    if DEBUG.load(Ordering::Relaxed) {
+        DEBUG_DRAWER.lock().unwrap().queue(f);
+    }
+}
+
+fn debug_flush() {
+    if DEBUG.load(Ordering::Relaxed) {
+        DEBUG_DRAWER.lock().unwrap().draw();
+    } else {
+        DEBUG_DRAWER.lock().unwrap().clear();
+    }
+}
+
+fn debug() -> bool {
+    DEBUG.load(Ordering::Relaxed)
+}
+
+fn debug_toggle() {
+    DEBUG.store(!DEBUG.load(Ordering::Relaxed), Ordering::Relaxed);
+}
+
+// `offset` shifts the tracked point, and is clamped along with it
+fn get_camera_target(scene: &Scene, offset: Vec2) -> Vec2 {
+    let target = scene
+        .level_center
+        .lerp(scene.player.pos, scene.camera_tracking)
+        + offset;
+
+    // keep the visible rect inside the level, or centered on it when the level is smaller
+    let (width, height) = get_width_height(scene.camera_height);
+    let half_view = vec2(width, height) / 2.0;
+    let bounds = scene.bounds;
+    let clamp_axis = |t: f32, min: f32, max: f32, half: f32| {
+        if max - min <= half * 2.0 {
+            (min + max) / 2.0
+        } else {
+            t.clamp(min + half, max - half)
+        }
+    };
+    vec2(
+        clamp_axis(target.x, bounds.left(), bounds.right(), half_view.x),
+        clamp_axis(target.y, bounds.top(), bounds.bottom(), half_view.y),
+    )
+}
+
+#[derive(Clone)]
+struct Projectile {
+    pos: Vec2,
+    dir: Vec2,
+    path: Vec<Intersection>,
+    length: f32,
+    ty: ArrowType,
+}
+
+impl Projectile {
+    fn new(pos: Vec2, path: &[Intersection], ty: ArrowType) -> Self {
+        assert!(!path.is_empty());
+        let dir = (path[0].pos - pos).normalize_or(vec2(1.0, 0.0));
+        Self {
+            pos,
+            dir,
+            path: path.to_vec(),
+            length: 16.0,
+            ty,
+        }
+    }
+
+    fn tick(&mut self, bounds: Rect, colliders: &[Rect], enemies: &mut [Enemy], dt: f32) -> bool {
+        let speed = 500.0;
+        let v = self.dir * speed * dt;
+        if !self.path.is_empty() && (self.path[0].pos - self.pos).length() <= v.length() {
+            self.pos = self.path[0].pos;
+            let removed = self.path.remove(0);
+            match removed.entity {
+                IsectType::Air => return true,
+                IsectType::Collider(_) => {
+                    play("arrow_bounce", 1.0, false);
+                }
+                _ => {}
+            }
+            if let Some(&next) = self.path.get(0).as_ref() {
+                self.dir = (next.pos - self.pos).normalize_or(vec2(1.0, 0.0));
+            }
+        } else {
+            self.pos += v;
+        }
+        // nothing left to hit once it's off the map, with a little slack for bounces off the edge
+        let slack = 1.0;
+        let bounds = Rect::new(
+            bounds.x - slack,
+            bounds.y - slack,
+            bounds.w + slack * 2.0,
+            bounds.h + slack * 2.0,
+        );
+        if !bounds.contains(self.pos) {
+            return true;
+        }
+        let head = self.pos + self.length * self.dir;
+        if self.path.is_empty() {
+            if let Some(_) = colliders
+                .iter()
+                .find(|c| c.contains(head) || c.contains(self.pos))
+            {
+                return true;
+            }
+        }
+        if let Some(e) = enemies
+            .iter_mut()
+            .find(|e| !e.dead() && (e.rect().contains(head) || e.rect().contains(self.pos)))
+        {
+            match self.ty {
+                ArrowType::Lethal => e.die(),
+                ArrowType::Tracker => {
+                    let tag_duration = 10.0;
+                    e.tagged_until = get_time() + tag_duration;
+                }
+            }
+            play("hit", 1.0, false);
+            return true;
+        }
+        if settings().arrows_hit_bodies {
+            if let Some(e) = enemies
+                .iter_mut()
+                .find(|e| e.dead() && (e.rect().contains(head) || e.rect().contains(self.pos)))
+            {
+                // the arrow stops in the body and shoves it a little along the way it was flying
+                let nudge = 6.0;
+                e.pos += slide_rect(e.collision_rect(), self.dir * nudge, colliders);
+                play("hit", 1.0, false);
+                return true;
+            }
+        }
+        false
+    }
+}
+
+impl Draw for Projectile {
+    fn draw(&self) {
+        let target = self.pos + self.dir * self.length;
+        let color = match self.ty {
+            ArrowType::Lethal => WHITE,
+            ArrowType::Tracker => YELLOW,
+        };
+        draw_line(self.pos.x, self.pos.y, target.x, target.y, 2.0, color);
+    }
+
+    fn sort_order(&self) -> f32 {
+        100000.0
+    }
+}
+
+enum ResetStage {
+    Initial,
+    ResetNow,
+    Done,
+}
+
+#[derive(Clone, Copy)]
+enum SwipeDirection {
+    Normal,
+    Reversed,
+}
+
+impl SwipeDirection {
+    fn sign(self) -> f32 {
+        match self {
+            Self::Normal => 1.0,
+            Self::Reversed => -1.0,
+        }
+    }
+}
+
+#[derive(Clone)]
+struct ResetHandler {
+    swipe_t: f32,
+    // how long it takes to cover and then uncover the screen, in seconds
+    swipe_duration: f32,
+    swipe_direction: SwipeDirection,
+    hit_middle: bool,
+    alarm_t: Time,
+    play_alarm: bool,
+    alarm: Option<SoundHandle>,
+}
+
+impl ResetHandler {
+    fn new(play_alarm: bool, swipe_duration: f32, swipe_direction: SwipeDirection) -> Self {
+        Self {
+            swipe_t: -1.0,
+            swipe_duration,
+            swipe_direction,
+            alarm_t: 0.0,
+            hit_middle: false,
+            play_alarm,
+            alarm: None,
+        }
+    }
+
+    // starts with the screen already covered, for uncovering a level that was just entered
+    fn revealing(swipe_duration: f32, swipe_direction: SwipeDirection) -> Self {
+        let mut handler = Self::new(false, swipe_duration, swipe_direction);
+        handler.swipe_t = 0.0;
+        handler.hit_middle = true;
+        handler
+    }
+
+    fn tick(&mut self, dt: f32) -> ResetStage {
+        if self.play_alarm && self.alarm_t == 0.0 {
+            self.alarm = Some(play("alarm", 1.0, true));
+        }
+        if self.play_alarm {
+            self.alarm_t += dt as Time;
+        }
+        if self.play_alarm && self.alarm_t < 1.5 {
+            return ResetStage::Initial;
+        }
+        self.swipe_t += dt * 2.0 / self.swipe_duration;
+        // the alarm fades out as the screen is covered
+        if let Some(alarm) = self.alarm {
+            set_handle_volume(alarm, (-self.swipe_t).clamp(0.0, 1.0));
+        }
+        if self.swipe_t > 0.0 && !self.hit_middle {
+            self.hit_middle = true;
+            if let Some(alarm) = self.alarm.take() {
+                stop_handle(alarm);
+            }
+            return ResetStage::ResetNow;
+        } else if self.swipe_t > 1.0 {
+            return ResetStage::Done;
+        }
+        ResetStage::Initial
+    }
+
+    fn alarm_t(&self) -> Time {
+        if self.hit_middle {
+            0.0
+        } else {
+            self.alarm_t
+        }
+    }
+}
+
+// a reset replaced before it reaches the middle would otherwise leave its alarm looping
+impl Drop for ResetHandler {
+    fn drop(&mut self) {
+        if let Some(alarm) = self.alarm.take() {
+            stop_handle(alarm);
+        }
+    }
+}
+
+// kept across resets, so they cover every attempt at a level until it is cleared
+#[derive(Clone, Default)]
+struct LevelStats {
+    time: Time,
+    detections: u32,
+    arrows: u32,
+}
+
+impl LevelStats {
+    fn grade(&self, enemy_count: usize) -> char {
+        let wasted_arrows = self.arrows.saturating_sub(enemy_count as u32);
+        let score =
+            100.0 - self.detections as f64 * 25.0 - wasted_arrows as f64 * 5.0 - self.time / 10.0;
+        match score {
+            s if s >= 85.0 => 'A',
+            s if s >= 70.0 => 'B',
+            s if s >= 55.0 => 'C',
+            s if s >= 40.0 => 'D',
+            _ => 'F',
+        }
+    }
+
+    fn medal(&self, medal_times: &[(Medal, Time)]) -> Option<Medal> {
+        medal_times
+            .iter()
+            .filter(|(_, t)| self.time <= *t)
+            .map(|(m, _)| *m)
+            .max()
+    }
+
+    fn summary(&self, enemy_count: usize, medal: Option<Medal>) -> String {
+        let minutes = (self.time / 60.0) as i32;
+        let seconds = self.time - minutes as f64 * 60.0;
+        let mut summary = format!(
+            "Stage cleared! Grade: {}. Time {:02}:{:05.2}, detected {} times, {} arrows fired.",
+            self.grade(enemy_count),
+            minutes,
+            seconds,
+            self.detections,
+            self.arrows,
+        );
+        if let Some(medal) = medal {
+            summary += &format!(" {} medal earned!", medal.name());
+        }
+        summary
+    }
+}
+
+struct Scene {
+    idx: usize,
+    enemies: Vec<Enemy>,
+    player: Player,
+    level_center: Vec2,
+    colliders: Colliders,
+    shadow_segments: Vec<(Vec2, Vec2)>,
+    // the same segments, for finding the ones near a guard
+    shadow_tree: QuadTree,
+    bg_color: Color,
+    ambient_color: Color,
+    dim_factor: f32,
+    structure_sprites: Vec<TileSprite>,
+    auto_sprites: Vec<TileSprite>,
+    decoration_sprites: Vec<TileSprite>,
+    popups: Vec<PopUp>,
+    one_way_windows: Vec<OneWayWindow>,
+    lights: Vec<Light>,
+    // the action the current tutorial popup is waiting for
+    popup_gate: Option<PopUpGate>,
+    triggers: Vec<Trigger>,
+    pathfinder: Pathfinder,
+    bounds: Rect,
+    projectiles: Vec<Projectile>,
+    reset_handler: Option<ResetHandler>,
+    level_name: String,
+    camera_height: f32,
+    camera_tracking: f32,
+    stage_cleared: bool,
+    player_in_trigger: bool,
+    stats: LevelStats,
+    medal_times: Vec<(Medal, Time)>,
+    win_condition: WinCondition,
+}
+
+impl Scene {
+    async fn new(
+        ldtk_str: &str,
+        idx: usize,
+        player_smap: &SpriteMap,
+        enemy_smaps: &[SpriteMap],
+        emote_smap: &SpriteMap,
+    ) -> Self {
+        let mut level = load_ldtk(ldtk_str, idx).await;
+        if settings().mirror_mode {
+            level.mirror();
+        }
+        let enemy_density = settings().enemy_density;
+        if enemy_density > 1.0 {
+            add_density_spawns(&mut level.patrol_paths, enemy_density);
+        }
+        let level_center = level.center;
+
+        let emotes = Emotes::new(emote_smap.clone());
+
+        let mut player = Player::new(
+            level.player_spawn,
+            player_smap.clone(),
+            emotes.clone().with_offset(PLAYER_EMOTE_OFFSET),
+        );
+        player.last_v = level.player_facing;
+        if level.player_facing.x != 0.0 {
+            player.last_dir_x = level.player_facing.x;
+        }
+
+        let mut enemies = level
+            .patrol_paths
+            .into_iter()
+            .map(|p| {
+                let smap = match p.skin {
+                    Some(skin) => &enemy_smaps[skin % enemy_smaps.len()],
+                    None => enemy_smaps.choose().unwrap(),
+                };
+                Enemy::new(
+                    p,
+                    smap.clone(),
+                    emotes.clone().with_offset(ENEMY_EMOTE_OFFSET),
+                )
+            })
+            .collect::<Vec<_>>();
+        let colliders = Colliders::new(shape_preserving_rect_merge(level.colliders));
+        let mut shadow_casters = shape_preserving_rect_merge(level.shadow_casters);
+        shadow_casters.push(level.bounds);
+        let shadow_segments = rects_to_segments(&shadow_casters).collect::<Vec<_>>();
+        let shadow_tree = QuadTree::build_around(QUADTREE_CELL, &shadow_segments);
+        let structure_sprites = level.structure_sprites;
+        let auto_sprites = level.auto_sprites;
+        let decoration_sprites = level.decoration_sprites;
+        let triggers = level.triggers;
+        let bounds = level.bounds;
+        let pathfinder = Pathfinder::new(
+            bounds.w,
+            bounds.h,
+            level.grid,
+            &colliders,
+            settings().diagonal_paths,
+        );
+        let level_name = level.level_name;
+        let camera_height = level.camera_height;
+        let camera_tracking = level.camera_tracking;
+        let popups = level.popups;
+        let one_way_windows = level.one_way_windows;
+        let lights = level.lights;
+        let medal_times = level.medal_times;
+        let win_condition = level.win_condition;
+        for e in enemies.iter_mut() {
+            e.move_to_first_node(&pathfinder);
+        }
+
+        Self {
+            idx,
+            enemies,
+            player,
+            level_center,
+            colliders,
+            shadow_segments,
+            shadow_tree,
+            bg_color: level.bg_color,
+            ambient_color: level.ambient_color,
+            dim_factor: level.dim_factor,
+            structure_sprites,
+            auto_sprites,
+            decoration_sprites,
+            popups,
+            one_way_windows,
+            lights,
+            popup_gate: None,
+            triggers,
+            pathfinder,
+            bounds,
+            projectiles: Vec::new(),
+            reset_handler: None,
+            level_name,
+            camera_height,
+            camera_tracking,
+            stage_cleared: false,
+            player_in_trigger: false,
+            stats: LevelStats::default(),
+            medal_times,
+            win_condition,
+        }
+    }
+
+    fn is_popup_gate_done(&self, gate: PopUpGate) -> bool {
+        match gate {
+            PopUpGate::Move => self.player.is_moving,
+            PopUpGate::Aim => self.player.strike_cone.is_some(),
+            PopUpGate::Shoot => !self.projectiles.is_empty(),
+            PopUpGate::PickUp => self.player.carrying.is_some(),
+            PopUpGate::SwitchArrow => is_key_pressed(KeyCode::Tab),
+        }
+    }
+
+    fn is_player_hidden(&self) -> bool {
+        let r = self.player.detection_rect();
+        !self.enemies.iter().any(|e| e.is_rect_in_cone(r))
+    }
+
+    fn check_trigger(&mut self, won_game: &mut bool) -> Option<String> {
+        if self.player.detected {
+            return None;
+        }
+        for trigger in self.triggers.iter() {
+            if trigger.rect.overlaps(&self.player.collision_rect()) {
+                match trigger.ty {
+                    TriggerType::LevelTransition => {
+                        if !self.player_in_trigger {
+                            self.player_in_trigger = true;
+                            return trigger.destination.clone();
+                        } else {
+                            return None;
+                        }
+                    }
+                    TriggerType::WonGame => {
+                        *won_game = true;
+                    }
+                }
+            }
+        }
+        self.player_in_trigger = false;
+        None
+    }
+
+    fn get_sorted_drawables(&self) -> Vec<&dyn Draw> {
+        let mut drawables = self
+            .decoration_sprites
+            .iter()
+            .map(|t| t as &dyn Draw)
+            .chain(self.enemies.iter().map(|e| e as &dyn Draw))
+            .chain([&self.player as &dyn Draw].into_iter())
+            .collect::<Vec<_>>();
+        drawables.sort_by(|a, b| a.sort_order().partial_cmp(&b.sort_order()).unwrap());
+        drawables
+    }
+
+    fn render_preview_texture(&self, width: f32, height: f32, show_routes: bool) -> RenderTarget {
+        let tex = render_target(width as u32, height as u32);
+        let mut camera = Camera2D::from_display_rect(Rect::new(0.0, height, width, -height));
+        camera.target = self.level_center;
+        camera.render_target = Some(tex.clone());
+        set_camera(&camera);
+
+        clear_background(self.bg_color);
+        for t in self.auto_sprites.iter() {
+            t.draw();
+        }
+        for t in self.structure_sprites.iter() {
+            t.draw();
+        }
+        for d in self.get_sorted_drawables() {
+            d.draw();
+        }
+        // gives a feel for how many guards there are and how much ground they cover
+        if show_routes {
+            for e in self.enemies.iter() {
+                e.draw_patrol_route();
+            }
+        }
+
+        set_default_camera();
+        tex
+    }
+
+    // whether the exits are open
+    fn is_objective_done(&self) -> bool {
+        match self.win_condition {
+            WinCondition::KillAll => self.enemies.iter().all(|e| e.dead()),
+            WinCondition::ReachExit => true,
+            WinCondition::KillTargets => self
+                .enemies
+                .iter()
+                .filter(|e| e.patrol_path.target)
+                .all(|e| e.dead()),
+        }
+    }
+
+    fn objective_hint(&self) -> &'static str {
+        match self.win_condition {
+            WinCondition::KillTargets => {
+                "You cannot progress until every target has been dispatched."
+            }
+            _ => "You cannot progress until all enemies have been dispatched.",
+        }
+    }
+
+    // returns true if the stage wasn't already cleared
+    fn clear_stage(&mut self) -> bool {
+        if self.stage_cleared {
+            return false;
+        }
+        play("win", 0.6, false);
+        self.stage_cleared = true;
+        emit(GameEvent::LevelCleared(self.level_name.clone()));
+        true
+    }
+
+    // returns true on the frame the stage is cleared
+    fn tick(&mut self, dt: f32) -> bool {
+        if self.stage_cleared {
+            return false;
+        }
+        self.stats.time += dt as Time;
+        // levels won by reaching an exit are cleared when the player gets there instead
+        if self.win_condition != WinCondition::ReachExit && self.is_objective_done() {
+            return self.clear_stage();
+        }
+        false
+    }
+
+    // after clearing a level by its objective, the exit is pointed out until the player leaves
+    fn show_exit_cue(&self) -> bool {
+        self.stage_cleared
+            && self.win_condition != WinCondition::ReachExit
+            && !self.enemies.is_empty()
+            && settings().clear_cue
+    }
+
+    fn nearest_exit(&self) -> Option<&Trigger> {
+        self.triggers.iter().min_by(|a, b| {
+            let da = (a.rect.center() - self.player.pos).length();
+            let db = (b.rect.center() - self.player.pos).length();
+            da.partial_cmp(&db).unwrap()
+        })
+    }
+
+    // a pulsing outline around the closest exit, with a bobbing chevron above it
+    fn draw_exit_cue(&self) {
+        if !self.show_exit_cue() {
+            return;
+        }
+        if let Some(exit) = self.nearest_exit() {
+            let pulse = ((get_time() * 4.0).sin() * 0.5 + 0.5) as f32;
+            let color = Color::new(MY_WHITE.r, MY_WHITE.g, MY_WHITE.b, 0.4 + 0.6 * pulse);
+            let r = exit.rect;
+            draw_rectangle_lines(r.x, r.y, r.w, r.h, 2.0, color);
+            let bob = (get_time() * 6.0).sin() as f32 * 2.0;
+            draw_chevron(vec2(r.center().x, r.y - 6.0 + bob), vec2(0.0, 1.0), color);
+        }
+    }
+
+    // everything a designer should fix about the level
+    fn validate(&self) -> Vec<String> {
+        let mut problems = Vec::new();
+        if self.win_condition == WinCondition::KillTargets
+            && !self.enemies.iter().any(|e| e.patrol_path.target)
+        {
+            problems.push("wins by killing targets but has none".to_string());
+        }
+        for e in self.enemies.iter() {
+            problems.extend(e.validate_patrol_path(&self.pathfinder));
+        }
+        problems
+    }
+
+    fn announce_clear(&self, ui: &mut Ui) {
+        if self.enemies.is_empty() {
+            return;
+        }
+        let medal = self.stats.medal(&self.medal_times);
+        if let Some(medal) = medal {
+            save_data().record_medal(&self.level_name, medal);
+        }
+        ui.popup(&self.stats.summary(self.enemies.len(), medal));
+    }
+
+    fn export_geometry(&self) -> GeometryDump {
+        let rect_to_array = |r: &Rect| [r.x, r.y, r.w, r.h];
+        GeometryDump {
+            level_name: self.level_name.clone(),
+            colliders: self.colliders.iter().map(rect_to_array).collect(),
+            shadow_segments: self
+                .shadow_segments
+                .iter()
+                .map(|(a, b)| [a.to_array(), b.to_array()])
+                .collect(),
+            patrol_paths: self
+                .enemies
+                .iter()
+                .map(|e| {
+                    e.patrol_path
+                        .nodes
+                        .iter()
+                        .map(|n| n.pos.to_array())
+                        .collect()
+                })
+                .collect(),
+            triggers: self
+                .triggers
+                .iter()
+                .map(|t| TriggerDump {
+                    rect: rect_to_array(&t.rect),
+                    ty: t.ty,
+                })
+                .collect(),
+        }
+    }
+}
+
+/// The merged level geometry of a scene, for feeding into external tools.
+#[derive(Serialize)]
+struct GeometryDump {
+    level_name: String,
+    colliders: Vec<[f32; 4]>,
+    shadow_segments: Vec<[[f32; 2]; 2]>,
+    patrol_paths: Vec<Vec<[f32; 2]>>,
+    triggers: Vec<TriggerDump>,
+}
+
+#[derive(Serialize)]
+struct TriggerDump {
+    rect: [f32; 4],
+    ty: TriggerType,
+}
+
+#[derive(Clone, Copy, Eq, PartialEq, Serialize)]
+pub enum TriggerType {
+    LevelTransition,
+    WonGame,
+}
+
+pub struct Trigger {
+    rect: Rect,
+    ty: TriggerType,
+    // the level a transition trigger leads to
+    destination: Option<String>,
+}
+
+type Anims = Vec<(String, Vec<Rect>, Time)>;
+
+fn emote_anims() -> Anims {
+    vec![
+        (
+            "base_intro".to_owned(),
+            anim_rects(Rect::new(0.0, 0.0, 32.0, 64.0), 32.0, 4),
+            0.1,
+        ),
+        (
+            "base_final".to_owned(),
+            anim_rects(Rect::new(32.0 * 3.0, 0.0, 32.0, 64.0), 32.0, 1),
+            0.3,
+        ),
+        (
+            "exclamation".to_owned(),
+            anim_rects(Rect::new(0.0, 32.0 * 5.0, 32.0, 32.0), 32.0, 2),
+            0.3,
+        ),
+        (
+            "question".to_owned(),
+            anim_rects(Rect::new(32.0 * 2.0, 32.0 * 6.0, 32.0, 32.0), 32.0, 2),
+            0.3,
+        ),
+        (
+            "knife".to_owned(),
+            anim_rects(Rect::new(32.0 * 2.0, 32.0 * 7.0, 32.0, 32.0), 32.0, 2),
+            0.3,
+        ),
+        (
+            "sweat".to_owned(),
+            anim_rects(Rect::new(32.0 * 6.0, 32.0 * 9.0, 32.0, 32.0), 32.0, 2),
+            0.3,
+        ),
+    ]
+}
+
+// the animations every character sheet shares
+fn char_anims() -> Anims {
+    let row_base = 12.0;
+    let row_height = 64.0;
+    let col_width = 32.0;
+    let sprite_width = 32.0;
+    let sprite_height = 52.0;
+
+    macro_rules! anim_rect {
+        ($name: expr, $row: expr, $col: expr, $count: expr, $time: expr) => {
+            (
+                $name.to_owned(),
+                anim_rects(
+                    Rect::new(
+                        $col as f32 * col_width,
+                        row_base + $row as f32 * row_height,
+                        sprite_width,
+                        sprite_height,
+                    ),
+                    col_width,
+                    $count,
+                ),
+                $time,
+            )
+        };
+    }
+
+    let char_anims = vec![
+        anim_rect!("idle_right", 1, 0, 6, 0.2),
+        anim_rect!("idle_up", 1, 6, 6, 0.2),
+        anim_rect!("idle_left", 1, 12, 6, 0.2),
+        anim_rect!("idle_down", 1, 18, 6, 0.2),
+        anim_rect!("run_right", 2, 0, 6, 0.1),
+        anim_rect!("run_up", 2, 6, 6, 0.1),
+        anim_rect!("run_left", 2, 12, 6, 0.1),
+        anim_rect!("run_down", 2, 18, 6, 0.1),
+        anim_rect!("use_phone", 6, 0, 12, 0.2),
+        anim_rect!("sit_right", 4, 0, 1, 0.1),
+        anim_rect!("sit_left", 4, 6, 1, 0.1),
+        anim_rect!("strike_right", 14, 0, 6, 0.1),
+        anim_rect!("strike_up", 14, 6, 6, 0.1),
+        anim_rect!("strike_left", 14, 12, 6, 0.1),
+        anim_rect!("strike_down", 14, 18, 6, 0.1),
+        anim_rect!("damage_right", 19, 0, 3, 0.2),
+        anim_rect!("damage_up", 19, 3, 3, 0.2),
+        anim_rect!("damage_left", 19, 6, 3, 0.2),
+        anim_rect!("damage_down", 19, 9, 3, 0.2),
+    ];
+
+    // glancing to either side and back, pieced together from the first idle frame of each
+    // direction, whose columns are right 0, up 6, left 12 and down 18
+    let idle_frame = |col: i32| {
+        Rect::new(
+            col as f32 * col_width,
+            row_base + row_height,
+            sprite_width,
+            sprite_height,
+        )
+    };
+    let look_arounds = [
+        ("look_around_right", [6, 0, 18, 0]),
+        ("look_around_up", [12, 6, 0, 6]),
+        ("look_around_left", [18, 12, 6, 12]),
+        ("look_around_down", [0, 18, 12, 18]),
+    ];
+    char_anims
+        .into_iter()
+        .chain(look_arounds.into_iter().map(|(name, cols)| {
+            (
+                name.to_owned(),
+                cols.into_iter().map(idle_frame).collect::<Vec<_>>(),
+                0.5,
+            )
+        }))
+        .collect::<Vec<_>>()
+}
+
+async fn get_smaps() -> (SpriteMap, [SpriteMap; 3], SpriteMap) {
+    let enemy_sheets = {
+        let enemy1_sheet = SpriteSheet::from_texture_path("assets/enemy1.png").await;
+        let enemy2_sheet = SpriteSheet::from_texture_path("assets/enemy2.png").await;
+        let enemy3_sheet = SpriteSheet::from_texture_path("assets/enemy3.png").await;
+        [enemy1_sheet, enemy2_sheet, enemy3_sheet]
+    };
+    let player_sheet = SpriteSheet::from_texture_path("assets/player.png").await;
+    let emote_sheet = SpriteSheet::from_texture_path("assets/emotes.png").await;
+
+    let char_anims = char_anims();
+    let emote_anims = emote_anims();
+
+    let player_smap = SpriteMap::new(&player_sheet, &char_anims);
+    let enemy_smaps = [
+        SpriteMap::new(&enemy_sheets[0], &char_anims),
+        SpriteMap::new(&enemy_sheets[1], &char_anims),
+        SpriteMap::new(&enemy_sheets[2], &char_anims),
+    ];
+    let emote_smap = SpriteMap::new(&emote_sheet, &emote_anims);
+
+    (player_smap, enemy_smaps, emote_smap)
+}
+
+// the visibility, cones and light targets, sized relative to the screen and stretched over it when blitted
+fn new_render_targets(render_scale: f32) -> (RenderTarget, RenderTarget, RenderTarget) {
+    let width = (screen_width() * render_scale) as u32;
+    let height = (screen_height() * render_scale) as u32;
+    let vis_target = render_target(width, height);
+    vis_target.texture.set_filter(FilterMode::Nearest);
+    let cones_target = render_target(width, height);
+    let light_target = render_target(width, height);
+    (vis_target, cones_target, light_target)
+}
+
+fn get_width_height(desired_height: f32) -> (f32, f32) {
+    if screen_height() * 16.0 / 9.0 < screen_width() {
+        let height = desired_height * screen_dpi_scale();
+        let width = height * screen_width() / screen_height();
+        (width, height)
+    } else {
+        let width = desired_height * 16.0 / 9.0 * screen_dpi_scale();
+        let height = width * screen_height() / screen_width();
+        (width, height)
+    }
+}
+
+pub async fn draw_progress(text: &str, t: f32) {
+    clear_background(Color::from_hex(0x404059));
+    draw_text_aligned(
+        text,
+        TextAlign::Center,
+        vec2(screen_width(), screen_height()) / 2.0 - vec2(0.0, 200.0),
+        None,
+        false,
+        TextParams {
+            font_size: 48,
+            ..Default::default()
+        },
+    );
+    let w = screen_width() / 2.0;
+    let h = 50.0;
+    let pos = vec2(screen_width(), screen_height()) / 2.0 - vec2(w / 2.0, h / 2.0);
+    draw_rectangle_lines(pos.x, pos.y, w, h, 2.0, WHITE);
+    draw_rectangle(pos.x, pos.y, w * t, h, WHITE);
+    next_frame().await;
+}
+
+#[derive(Default)]
+struct AverageFps {
+    sum: i32,
+    count: i32,
+    last_reset: Time,
+    last_fps: f32,
+}
+
+impl AverageFps {
+    fn new() -> Self {
+        Self {
+            sum: 0,
+            count: 0,
+            last_reset: get_time(),
+            last_fps: 0.0,
+        }
+    }
+
+    fn tick(&mut self) {
+        if get_time() - self.last_reset > 1.0 {
+            self.last_fps = self.sum as f32 / self.count as f32;
+            self.last_reset = get_time();
+            self.sum = 0;
+            self.count = 0;
+        }
+        self.sum += get_fps();
+        self.count += 1;
+    }
+
+    fn get(&self) -> f32 {
+        self.last_fps
+    }
+}
+
+/// The problems with every level in the ldtk file, each prefixed with the level's name.
+fn validate_ldtk(scenes: &[Scene]) -> Vec<String> {
+    scenes
+        .iter()
+        .flat_map(|s| {
+            s.validate()
+                .into_iter()
+                .map(|p| format!("{}: {p}", s.level_name))
+        })
+        .collect()
+}
+
+pub async fn run() {
+    // macroquad::rand::srand(::rand::random::<u64>());
+    load_sounds().await;
+    preload_textures().await;
+
+    let (player_smap, enemy_smaps, emote_smap) = get_smaps().await;
+
+    // the options persist between sessions in the save
+    let loaded = Settings::load();
+    set_master_volume(loaded.master_volume);
+    *settings() = loaded;
+
+    let mut render_scale = settings().render_scale();
+    let (mut vis_target, mut cones_target, mut light_target) = new_render_targets(render_scale);
+    let mut postprocess_material = shadow_postprocess();
+
+    let mut shadows = settings().shadows;
+    let mut practice_overlay = false;
+    // when the last kill slowed the game down
+    let mut slow_mo_t = Option::<Time>::None;
+    let mut timer = Option::<Time>::None;
+    let mut won_game = false;
+    let mut is_debug_paused = false;
+    // run a single update while debug paused
+    let mut debug_step = false;
+    let mut free_camera = false;
+    let mut last_mouse_pos = Vec2::from(mouse_position());
+    // how far the camera currently leans towards the cursor while aiming
+    let mut aim_pan = vec2(0.0, 0.0);
+    // the run being recorded for a bug report, see replay.rs
+    let mut recording = Option::<Recording>::None;
+
+    let mut scenes = Vec::new();
+    let ldtk_str = include_str!("../assets/level.ldtk");
+    for idx in get_level_indices(ldtk_str).await {
+        scenes.push(Scene::new(ldtk_str, idx, &player_smap, &enemy_smaps, &emote_smap).await);
+    }
+    scenes.sort_by(|a, b| a.level_name.cmp(&b.level_name));
+    for problem in validate_ldtk(&scenes) {
+        eprintln!("warning: {problem}");
+    }
+    let scene_previews = {
+        let (width, height) = get_width_height(512.0);
+        scenes
+            .iter()
+            .filter(|s| s.level_name.starts_with("Stage_"))
+            .map(|s| {
+                (
+                    s.level_name.clone(),
+                    s.render_preview_texture(width, height, false),
+                    s.render_preview_texture(width, height, true),
+                )
+            })
+            .collect::<Vec<_>>()
+    };
+    let mut ui = Ui::new(scene_previews.clone()).await;
+    let mut scene = scenes.iter_mut().find(|s| s.level_name == "Menu").unwrap();
+    scene.player.pos = vec2(-100.0, -100.0);
+
+    let new_camera = |scene: &Scene| {
+        let (width, height) = get_width_height(scene.camera_height);
+        Camera2D::from_display_rect(Rect::new(0.0, height, width, -height))
+    };
+    let mut camera = new_camera(scene);
+
+    // a replay passed on the command line starts from the level and seed it was recorded with
+    if let Some((seed, level)) = replay_path().and_then(|path| start_playback(&path)) {
+        scene = scenes
+            .iter_mut()
+            .find(|s| s.level_name == level)
+            .expect("the replay's level is missing");
+        macroquad::rand::srand(seed);
+        *scene = Scene::new(ldtk_str, scene.idx, &player_smap, &enemy_smaps, &emote_smap).await;
+        camera = new_camera(scene);
+        ui.set_curr_level(&level);
+        ui.close();
+    }
+
+    let mut last_screen_size = vec2(screen_width(), screen_height());
+
+    play("bg_music", 1.0, true);
+
+    let mut avg_fps = AverageFps::new();
+
+    loop {
+        // a replay being played back stands in for the frame time as well as the input
+        let frame_time = advance_playback().unwrap_or_else(get_frame_time);
+        avg_fps.tick();
+        update_loops();
+        if let Some(timer) = timer.as_mut() {
+            if !won_game && !ui.is_enabled() {
+                *timer += frame_time as Time;
+                save_data().add_playtime(frame_time as f64);
+            }
+        }
+
+        if last_screen_size != vec2(screen_width(), screen_height())
+            || render_scale != settings().render_scale()
+        {
+            render_scale = settings().render_scale();
+            set_default_camera();
+            camera.render_target = None;
+            gl_use_default_material();
+            last_screen_size = vec2(screen_width(), screen_height());
+            let old_target = camera.target;
+            camera = new_camera(scene);
+            camera.target = old_target;
+            drop(postprocess_material);
+            drop(vis_target);
+            drop(cones_target);
+            drop(light_target);
+            next_frame().await;
+            postprocess_material = shadow_postprocess();
+            (vis_target, cones_target, light_target) = new_render_targets(render_scale);
+            ui.resize();
+        }
+
+        if cfg!(debug_assertions) {
+            if is_key_pressed(KeyCode::Q) {
+                break;
+            }
+            if is_key_pressed(KeyCode::Space) {
+                is_debug_paused = !is_debug_paused;
+            }
+            debug_step = is_debug_paused && is_key_pressed(KeyCode::N);
+            // starts recording from a fresh load of the level with a new seed, or stops and saves
+            if is_key_pressed(KeyCode::F9) {
+                if let Some(r) = recording.take() {
+                    r.save();
+                } else {
+                    let seed = miniquad::date::now() as u64;
+                    macroquad::rand::srand(seed);
+                    *scene =
+                        Scene::new(ldtk_str, scene.idx, &player_smap, &enemy_smaps, &emote_smap)
+                            .await;
+                    recording = Some(Recording::new(seed, &scene.level_name));
+                }
+            }
+            if is_key_pressed(KeyCode::F) {
+                free_camera = !free_camera;
+                if !free_camera {
+                    camera.target = get_camera_target(scene, aim_pan);
+                }
+            }
+            if free_camera && !ui.is_enabled() {
+                let pan_speed = 400.0;
+                let mut pan = vec2(0.0, 0.0);
+                // the arrow keys turn the bow while it's drawn from the keyboard
+                let keys_free = scene.player.keyboard_aim.is_none();
+                if keys_free && is_key_down(KeyCode::Up) {
+                    pan.y -= 1.0;
+                }
+                if keys_free && is_key_down(KeyCode::Down) {
+                    pan.y += 1.0;
+                }
+                if keys_free && is_key_down(KeyCode::Left) {
+                    pan.x -= 1.0;
+                }
+                if keys_free && is_key_down(KeyCode::Right) {
+                    pan.x += 1.0;
+                }
+                camera.target += pan.normalize_or_zero() * pan_speed * get_frame_time();
+                if is_mouse_button_down(MouseButton::Middle) {
+                    camera.target += camera.screen_to_world(last_mouse_pos)
+                        - camera.screen_to_world(mouse_position().into());
+                }
+            }
+        }
+        last_mouse_pos = mouse_position().into();
+
+        let mut new_scene = None;
+        // also covers the first frame after a pause, which can be as long as the pause itself
+        let dt = if debug_step {
+            1.0 / 60.0
+        } else {
+            frame_time.min(MAX_DT)
+        };
+        if let Some(r) = recording.as_mut() {
+            r.record_frame(frame_time);
+        }
+        let time_scale = slow_mo_t.and_then(slow_mo_scale);
+        slow_mo_t = time_scale.and(slow_mo_t.map(|t| t + dt as Time));
+        let dt = dt * time_scale.unwrap_or(1.0);
+
+        if frame_time > FOCUS_LOST_FRAME_TIME && scene.level_name != "Menu" && !ui.is_enabled() {
+            ui.pause();
+            stop_all();
+        }
+
+        if !ui.is_enabled() && (!is_debug_paused || debug_step) {
+            if is_key_pressed(KeyCode::R) {
+                scene.reset_handler = Some(ResetHandler::new(false, 1.0, SwipeDirection::Normal));
+            }
+            if is_key_pressed(KeyCode::P) {
+                practice_overlay = !practice_overlay;
+            }
+            if cfg!(debug_assertions) {
+                if is_key_pressed(KeyCode::T) {
+                    shadows = !shadows;
+                    let mut settings = settings();
+                    settings.shadows = shadows;
+                    settings.save();
+                }
+                if is_key_pressed(KeyCode::G) {
+                    unsafe {
+                        ROTATING = !ROTATING;
+                    }
+                }
+                if is_key_pressed(KeyCode::H) {
+                    debug_toggle();
+                }
+                if is_key_pressed(KeyCode::X) {
+                    let path = format!("{}_geometry.json", scene.level_name);
+                    let dump = serde_json::to_string_pretty(&scene.export_geometry()).unwrap();
+                    if let Err(e) = std::fs::write(&path, dump) {
+                        eprintln!("could not write {path}: {e}");
+                    }
+                }
+            }
+
+            scene.player.tick(
+                &mut camera,
+                &scene.colliders,
+                &scene.pathfinder,
+                &mut scene.projectiles,
+                &mut scene.enemies,
+                dt,
+            );
+            let max_projectiles = settings().max_projectiles;
+            if scene.projectiles.len() > max_projectiles {
+                let excess = scene.projectiles.len() - max_projectiles;
+                scene.projectiles.drain(..excess);
+            }
+            // drawing the bow with the mouse leans the camera towards the cursor, so long bank
+            // shots stay on screen
+            let aim_pan_target = if scene.player.strike_cone.is_some()
+                && is_mouse_button_down(MouseButton::Left)
+            {
+                let cursor_off = camera.screen_to_world(mouse_position().into()) - camera.target;
+                (cursor_off * AIM_PAN_FRACTION).clamp_length_max(AIM_PAN_MAX)
+            } else {
+                vec2(0.0, 0.0)
+            };
+            aim_pan += (aim_pan_target - aim_pan) * (dt * AIM_PAN_SPEED).min(1.0);
+            if !free_camera {
+                camera.target = get_camera_target(scene, aim_pan);
+            }
+            let dead_enemy_rects = scene
+                .enemies
+                .iter()
+                .filter(|e| e.dead())
+                .map(|e| e.detection_rect())
+                .collect::<Vec<_>>();
+            for e in scene.enemies.iter_mut() {
+                // walls out of the cone's reach can't cut it
+                let nearby = scene
+                    .shadow_tree
+                    .filter_by_radius(e.pos, e.detection_radius);
+                let segments = segments_seen_from(e.pos, &nearby, &scene.one_way_windows);
+                e.tick(
+                    &scene.pathfinder,
+                    &scene.colliders,
+                    &segments,
+                    &dead_enemy_rects,
+                    &mut scene.player,
+                    dt,
+                );
+            }
+            scene.projectiles = scene
+                .projectiles
+                .drain(..)
+                .filter_map(|mut p| {
+                    if !p.tick(scene.bounds, &scene.colliders, &mut scene.enemies, dt) {
+                        Some(p)
+                    } else {
+                        None
+                    }
+                })
+                .collect();
+
+            if scene.player.detected {
+                if settings().permadeath {
+                    ui.game_over(&scene.level_name, timer.unwrap_or(0.0));
+                } else if scene.reset_handler.is_none() {
+                    scene.stats.detections += 1;
+                    scene.reset_handler =
+                        Some(ResetHandler::new(true, 1.0, SwipeDirection::Normal));
+                }
+            }
+            if scene.tick(dt) {
+                scene.announce_clear(&mut ui);
+            }
+
+            if let Some(gate) = scene.popup_gate {
+                if scene.is_popup_gate_done(gate) {
+                    play("menu_select", 1.0, false);
+                    scene.popup_gate = None;
+                    ui.clear_hint();
+                }
+            }
+
+            // popups wait until the gated one before them is done, and for the screen to uncover
+            for pop in scene.popups.iter_mut().filter(|p| !p.triggered) {
+                if scene.popup_gate.is_some() || scene.reset_handler.is_some() {
+                    break;
+                }
+                if !ui.is_enabled() && pop.rect.overlaps(&scene.player.collision_rect()) {
+                    pop.triggered = true;
+                    if let Some(gate) = pop.gate {
+                        scene.popup_gate = Some(gate);
+                        ui.show_hint(&pop.text);
+                    } else {
+                        scene.player.state = PlayerState::Idle;
+                        scene.player.is_moving = false;
+                        ui.popup(&pop.text);
+                    }
+                }
+            }
+
+            if let Some(handler) = scene.reset_handler.as_mut() {
+                match handler.tick(dt) {
+                    ResetStage::Initial => {}
+                    ResetStage::ResetNow => {
+                        let handler = handler.clone();
+                        let old_popups = scene.popups.clone();
+                        let old_popup_gate = scene.popup_gate;
+                        let old_stats = scene.stats.clone();
+                        *scene = Scene::new(
+                            &ldtk_str,
+                            scene.idx,
+                            &player_smap,
+                            &enemy_smaps,
+                            &emote_smap,
+                        )
+                        .await;
+                        scene.popups = old_popups;
+                        scene.popup_gate = old_popup_gate;
+                        scene.stats = old_stats;
+                        scene.reset_handler = Some(handler);
+                    }
+                    ResetStage::Done => {
+                        scene.reset_handler = None;
+                    }
+                }
+            }
+        }
+
+        for event in drain_events() {
+            match event {
+                GameEvent::ArrowFired => scene.stats.arrows += 1,
+                GameEvent::EnemyKilled(_) => {
+                    save_data().record_kill();
+                    if settings().kill_slow_mo {
+                        slow_mo_t = Some(0.0);
+                    }
+                }
+                GameEvent::PlayerDetected => save_data().record_detection(),
+                GameEvent::LevelCleared(name) => save_data().record_clear(&name),
+                _ => {}
+            }
+        }
+
+        //
+        // DRAW TO THE VISIBILITY TEXTURE
+        //
+
+        camera.render_target = Some(vis_target.clone());
+        set_camera(&camera);
+        clear_background(Color::new(0.0, 0.0, 0.0, 0.0));
+
+        for t in scene.auto_sprites.iter() {
+            t.draw();
+        }
+        for t in scene.structure_sprites.iter() {
+            t.draw();
+        }
+
+        for d in scene.get_sorted_drawables() {
+            d.draw();
+        }
+
+        for e in scene.enemies.iter() {
+            e.draw_cone_outline();
+        }
+
+        //
+        // DRAW TO THE CONES TEXTURE
+        //
+        camera.render_target = Some(cones_target.clone());
+        set_camera(&camera);
+        clear_background(Color::new(0.0, 0.0, 0.0, 0.0));
+
+        for e in scene.enemies.iter() {
+            e.draw_cone();
+        }
+        for e in scene.enemies.iter() {
+            e.draw_red_cone();
+        }
+
+        //
+        // DRAW TO THE LIGHT TEXTURE
+        //
+        camera.render_target = Some(light_target.clone());
+        set_camera(&camera);
+        // levels without lights are left fully lit
+        if scene.lights.is_empty() {
+            clear_background(WHITE);
+        } else {
+            clear_background(BLACK);
+        }
+
+        for l in scene.lights.iter() {
+            l.draw();
+        }
+
+        // let max_distance = 1024.0;
+        // let directions = scene
+        //     .shadow_segments
+        //     .iter()
+        //     .map(|(a, b)| [*a, *b])
+        //     .flatten()
+        //     .map(|p| {
+        //         (p - scene.player.pos)
+        //             .angle_between(vec2(1.0, 0.0))
+        //             .normalized_rads()
+        //     });
+        // let poly = generate_shadow_polygon(
+        //     scene.player.pos,
+        //     &scene.shadow_segments,
+        //     directions,
+        //     max_distance,
+        //     true,
+        //     WHITE,
+        //     None,
+        // );
+        // draw_custom_shape(&poly.vertices, &poly.indices);
+
+        //
+        // BLIT THE TEXTURES TOGETHER
+        //
+
+        postprocess_material.set_texture("VisibleTexture", vis_target.texture.clone());
+        postprocess_material.set_texture("ConesTexture", cones_target.texture.clone());
+        postprocess_material.set_texture("LightTexture", light_target.texture.clone());
+        postprocess_material.set_uniform::<f32>("LightFloor", LIGHT_FLOOR);
+        postprocess_material.set_uniform::<f32>(
+            "AlarmTime",
+            scene
+                .reset_handler
+                .as_ref()
+                .map(|r| r.alarm_t())
+                .unwrap_or(0.0) as f32,
+        );
+        postprocess_material.set_uniform::<[f32; 4]>("BgColor", Color::from_hex(0x404059).into());
+        postprocess_material.set_uniform::<[f32; 4]>("AmbientColor", scene.ambient_color.into());
+        postprocess_material.set_uniform::<f32>("DimFactor", scene.dim_factor);
+        postprocess_material.set_uniform::<f32>("VignetteStrength", settings().vignette());
+        postprocess_material.set_uniform::<f32>(
+            "SwipeT",
+            scene
+                .reset_handler
+                .as_ref()
+                .map(|r| r.swipe_t)
+                .unwrap_or(-2.0),
+        );
+        postprocess_material.set_uniform::<f32>(
+            "SwipeDir",
+            scene
+                .reset_handler
+                .as_ref()
+                .map(|r| r.swipe_direction.sign())
+                .unwrap_or(1.0),
+        );
+        gl_use_material(&postprocess_material);
+        set_default_camera();
+        draw_rectangle(0.0, 0.0, screen_width(), screen_height(), WHITE);
+        gl_use_default_material();
+
+        //
+        // DRAW DEBUG INFO
+        //
+
+        camera.render_target = None;
+        set_camera(&camera);
+        debug_flush();
+        if debug() {
+            for c in scene.colliders.iter() {
+                draw_rectangle_lines(c.x, c.y, c.w, c.h, 1.0, BLUE);
+            }
+            for p in scene.popups.iter() {
+                draw_rectangle_lines(p.rect.x, p.rect.y, p.rect.w, p.rect.h, 1.0, PURPLE);
+            }
+        }
+
+        for e in scene.enemies.iter() {
+            e.draw_tag_outline();
+        }
+        for p in scene.projectiles.iter() {
+            p.draw();
+        }
+        scene.player.draw_bow(&camera);
+
+        // drawn after the blit so the shader doesn't dim them in dark areas
+        scene.player.draw_emotes();
+        for e in scene.enemies.iter() {
+            e.draw_emotes();
+            e.draw_turn_telegraph();
+        }
+        if settings().facing_markers {
+            for e in scene.enemies.iter() {
+                e.draw_facing_marker();
+            }
+        }
+        if practice_overlay {
+            for e in scene.enemies.iter() {
+                e.draw_patrol_route();
+            }
+        }
+        scene.draw_exit_cue();
+        if settings().reveal_culprit {
+            if let Some(id) = scene.player.caught_by {
+                if let Some(e) = scene.enemies.iter().find(|e| e.id == id) {
+                    e.draw_culprit_marker();
+                }
+            }
+        }
+
+        //
+        // DRAW UI
+        //
+
+        set_default_camera();
+        draw_text(
+            &format!("FPS: {:.0}", avg_fps.get()),
+            10.0,
+            25.0,
+            32.0,
+            MY_WHITE,
+        );
+
+        {
+            let timer = timer.unwrap_or(0.0);
+            let minutes = (timer / 60.0) as i32;
+            let seconds = timer - minutes as f64 * 60.0;
+            draw_text(
+                &format!("Timer: {:02}:{:05.2}", minutes, seconds),
+                10.0,
+                50.0,
+                32.0,
+                MY_WHITE,
+            );
+        }
+
+        draw_text(
+            match scene.player.arrow_type {
+                ArrowType::Lethal => "Arrow: Lethal",
+                ArrowType::Tracker => "Arrow: Tracker",
+            },
+            10.0,
+            75.0,
+            32.0,
+            MY_WHITE,
+        );
+
+        if scene.is_player_hidden() {
+            draw_text("Hidden", 10.0, 100.0, 32.0, MY_WHITE);
+        } else {
+            draw_text("Exposed", 10.0, 100.0, 32.0, MY_RED);
+        }
+
+        if scene.player.stamina() < 1.0 {
+            let bar_width = 150.0;
+            draw_rectangle_lines(10.0, 110.0, bar_width + 2.0, 12.0, 1.0, MY_WHITE);
+            draw_rectangle(
+                11.0,
+                111.0,
+                bar_width * scene.player.stamina(),
+                10.0,
+                MY_WHITE,
+            );
+        }
+
+        if scene.show_exit_cue() {
+            draw_text_aligned(
+                "Area clear - head to the exit",
+                TextAlign::Center,
+                vec2(screen_width() / 2.0, 50.0),
+                None,
+                false,
+                TextParams {
+                    font_size: 48,
+                    color: MY_WHITE,
+                    ..Default::default()
+                },
+            );
+        }
+
+        if scene.player.detected {
+            draw_text_aligned(
+                if let PlayerState::Caught = scene.player.state {
+                    "You were found!"
+                } else {
+                    "Dead body found!"
+                },
+                TextAlign::Center,
+                vec2(screen_width() / 2.0, screen_height() / 2.0),
+                None,
+                false,
+                TextParams {
+                    font_size: 128,
+                    ..Default::default()
+                },
+            );
+        }
+
+        set_camera(&ui.camera);
+        if let Some(s) = scene.check_trigger(&mut won_game) {
+            if scene.is_objective_done() {
+                if scene.clear_stage() {
+                    scene.announce_clear(&mut ui);
+                }
+                new_scene = Some(s);
+            } else {
+                scene.player.state = PlayerState::Idle;
+                scene.player.is_moving = false;
+                ui.popup(scene.objective_hint());
+            }
+        }
+        match ui.tick(scene.level_name == "Menu") {
+            UiAction::SwitchLevel(name) => {
+                save_data().record_run();
+                timer = Some(0.0);
+                won_game = false;
+                new_scene = Some(name);
+            }
+            UiAction::Quit => {
+                if scene.level_name == "Menu" {
+                    save_data().save();
+                    break;
+                } else {
+                    new_scene = Some("Menu".into());
+                }
+            }
+            UiAction::None => {}
+        }
+        ui.draw(scene.level_name == "Menu");
+
+        set_default_camera();
+
+        if let Some(new_scene) = new_scene {
+            let old_idx = scene.idx;
+            scene = scenes
+                .iter_mut()
+                .find(|s| s.level_name == new_scene)
+                .unwrap();
+            *scene = Scene::new(
+                &ldtk_str,
+                scene.idx,
+                &player_smap,
+                &enemy_smaps,
+                &emote_smap,
+            )
+            .await;
+            camera = new_camera(scene);
+
+            if scene.level_name == "Menu" {
+                scene.player.pos = vec2(-10000.0, -10000.0);
+            } else {
+                // going back to an earlier level swipes the other way
+                let direction = if scene.idx < old_idx {
+                    SwipeDirection::Reversed
+                } else {
+                    SwipeDirection::Normal
+                };
+                scene.reset_handler = Some(ResetHandler::revealing(1.0, direction));
+            }
+            ui.set_curr_level(&scene.level_name);
+            ui.clear_hint();
+
+            stop_all();
+        }
+
+        next_frame().await
+    }
+}
+
+/// Hooks for the benchmarks in `benches/`, which can't reach the game's internals otherwise.
+#[doc(hidden)]
+pub mod bench {
+    use macroquad::prelude::*;
+
+    use crate::{
+        geometry_utils::{shape_preserving_rect_merge, GeoUtilsFloatExts},
+        ldtk::LevelGeometry,
+        rects_to_segments, Polygon,
+    };
+
+    // the same shadow segments a `Scene` builds for the level
+    pub fn shadow_segments(level: &LevelGeometry) -> Vec<(Vec2, Vec2)> {
+        let mut shadow_casters = shape_preserving_rect_merge(level.shadow_casters.clone());
+        shadow_casters.push(level.bounds);
+        rects_to_segments(&shadow_casters).collect()
+    }
+
+    pub fn merged_colliders(level: &LevelGeometry) -> Vec<Rect> {
+        shape_preserving_rect_merge(level.colliders.clone())
+    }
+
+    // a full circle of light at `pos`, cast towards every segment endpoint
+    pub fn shadow_polygon(pos: Vec2, segments: &[(Vec2, Vec2)], max_distance: f32) -> Polygon {
+        let directions = segments
+            .iter()
+            .flat_map(|(a, b)| [*a, *b])
+            .map(|p| (p - pos).angle_between(vec2(1.0, 0.0)).normalized_rads())
+            .collect::<Vec<_>>();
+        crate::generate_shadow_polygon(pos, segments, directions, max_distance, true, WHITE, None)
+    }
+
+    // a guard's view cone with the default view angle and detection radius
+    pub fn guard_cone(pos: Vec2, facing: f32, segments: &[(Vec2, Vec2)]) -> Polygon {
+        crate::generate_view_cone(pos, facing, 90.0f32.to_radians(), segments, BLACK, 512.0)
+    }
+}
+
+pub fn window_conf() -> macroquad::conf::Conf {
+    macroquad::conf::Conf {
+        miniquad_conf: miniquad::conf::Conf {
+            window_title: "Museum Assassin".to_owned(),
+            window_width: 1920,
+            window_height: 1080,
+            high_dpi: true, // https://docs.rs/good-web-game/latest/good_web_game/
+            ..Default::default()
+        },
+        draw_call_index_capacity: 10000,
+        default_filter_mode: FilterMode::Nearest,
+        ..Default::default()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn assessment_climbs_by_dt_over_assess_duration() {
+        let dt = 0.1;
+        let t = assess_step(0.25, 90.0, 100.0, dt).unwrap();
+        assert!((t - (0.25 + dt / ASSESS_DURATION)).abs() < 1e-6);
+    }
+
+    #[test]
+    fn assessment_reports_at_the_suspects_share_of_the_detection_radius() {
+        let (dist, detection_radius, dt) = (50.0, 100.0, 1.0 / 60.0);
+        let mut t = 0.0;
+        while let Some(next) = assess_step(t, dist, detection_radius, dt) {
+            assert!(next < dist / detection_radius);
+            t = next;
+        }
+        // the report comes on the step that carries t up to dist / detection_radius
+        assert!(t + dt / ASSESS_DURATION >= dist / detection_radius);
+    }
+
+    #[test]
+    fn assessment_past_the_detection_radius_still_ends() {
+        assert_eq!(assess_step(0.99, 150.0, 100.0, 0.1), None);
+        assert!(assess_step(0.5, 150.0, 100.0, 0.1).is_some());
+    }
+
+    fn patrol_path(len: usize, full_circle: bool) -> PatrolPath {
+        PatrolPath {
+            nodes: (0..len)
+                .map(|i| PatrolNode {
+                    pos: vec2(i as f32 * 32.0, 0.0),
+                    facing: 0.0,
+                    wait: 1.0,
+                    walk: Some(1.0),
+                })
+                .collect(),
+            curr: 0,
+            timer: None,
+            forwards: true,
+            full_circle,
+            speed: None,
+            skin: None,
+            memory: None,
+            target: false,
+            cone_color: None,
+            report_speed: None,
+            scan_arc: None,
+            scan_period: None,
+        }
+    }
+
+    fn visits(path: &mut PatrolPath, steps: usize) -> Vec<usize> {
+        (0..steps).map(|_| path.advance()).collect()
+    }
+
+    #[test]
+    fn single_node_patrol_stays_put() {
+        let mut path = patrol_path(1, false);
+        assert_eq!(path.step(), (0, true));
+        assert_eq!(visits(&mut path, 3), vec![0, 0, 0]);
+    }
+
+    #[test]
+    fn two_node_patrol_goes_back_and_forth() {
+        let mut path = patrol_path(2, false);
+        assert_eq!(path.upcoming_node(), 1);
+        assert_eq!(visits(&mut path, 4), vec![1, 0, 1, 0]);
+        // it came back to node 0, and turns around again on the next step
+        assert!(!path.forwards);
+        assert_eq!(path.step(), (1, true));
+    }
+
+    #[test]
+    fn patrol_going_backwards_from_node_0_wraps_with_rem_euclid() {
+        // only a single node can head backwards from node 0, anything longer turns around
+        let mut path = patrol_path(1, false);
+        path.forwards = false;
+        assert_eq!(path.step(), (0, false));
+
+        let mut path = patrol_path(3, false);
+        path.forwards = false;
+        assert_eq!(path.step(), (1, true));
+
+        let mut path = patrol_path(3, true);
+        path.curr = 2;
+        assert_eq!(visits(&mut path, 3), vec![0, 1, 2]);
+    }
+
+    // sprites over a texture that's never drawn, so the tests don't need a gl context
+    fn sprite_map(anims: Anims) -> SpriteMap {
+        let texture = Texture2D::from_miniquad_texture(miniquad::TextureId::from_raw_id(
+            miniquad::RawId::OpenGl(0),
+        ));
+        SpriteMap::new(
+            &SpriteSheet {
+                texture,
+                data: None,
+            },
+            &anims,
+        )
+    }
+
+    fn emotes() -> Emotes {
+        Emotes::new(sprite_map(emote_anims()))
+    }
+
+    fn enemy_at(pos: Vec2) -> Enemy {
+        let mut path = patrol_path(1, false);
+        path.nodes[0].pos = pos;
+        Enemy::new(path, sprite_map(char_anims()), emotes())
+    }
+
+    const BOUNDS: Rect = Rect {
+        x: -512.0,
+        y: -512.0,
+        w: 1024.0,
+        h: 1024.0,
+    };
+
+    #[test]
+    fn emotes_sit_centered_above_the_sprite_plus_their_offset() {
+        let sprite_top_left = vec2(100.0, 200.0);
+        let sprite_size = vec2(48.0, 40.0);
+        let emote_size = emotes().base_final.size();
+        let centered = vec2(
+            sprite_top_left.x + (sprite_size.x - emote_size.x) / 2.0,
+            sprite_top_left.y - emote_size.y,
+        );
+        assert_eq!(emotes().anchor(sprite_top_left, sprite_size), centered);
+
+        let offset = vec2(-3.0, 8.0);
+        let nudged = emotes().with_offset(offset);
+        assert_eq!(
+            nudged.anchor(sprite_top_left, sprite_size),
+            centered + offset
+        );
+    }
+
+    #[test]
+    fn arrow_still_hits_after_the_enemies_grow() {
+        let mut enemies = vec![enemy_at(vec2(200.0, 0.0))];
+        let colliders = Colliders::new(Vec::new());
+        let path = get_intersections(vec2(0.0, 0.0), vec2(1.0, 0.0), &enemies, &colliders);
+        assert!(matches!(path[0].entity, IsectType::Enemy(0)));
+        let mut arrow = Projectile::new(vec2(0.0, 0.0), &path, ArrowType::Lethal);
+
+        // a guard spawning mid flight reallocates the enemies, which the arrow only knows by index
+        arrow.tick(BOUNDS, &colliders, &mut enemies, 0.1);
+        enemies.reserve(64);
+        enemies.push(enemy_at(vec2(0.0, 300.0)));
+
+        let mut hit = false;
+        for _ in 0..20 {
+            if arrow.tick(BOUNDS, &colliders, &mut enemies, 1.0 / 60.0) {
+                hit = true;
+                break;
+            }
+        }
+        assert!(hit);
+        assert!(enemies[0].dead());
+        assert!(!enemies[1].dead());
+    }
+
+    fn player_at(pos: Vec2) -> Player {
+        Player::new(pos, sprite_map(char_anims()), emotes())
+    }
+
+    fn pathfinder_over(colliders: &[Rect]) -> Pathfinder {
+        Pathfinder::new(512.0, 512.0, pathfinder::Grid::default(), colliders, false)
+    }
+
+    #[test]
+    fn body_across_a_thin_wall_is_out_of_reach_and_out_of_sight() {
+        let wall = Rect::new(112.0, 40.0, 8.0, 120.0);
+        let mut body = enemy_at(vec2(136.0, 100.0));
+        body.die();
+        let enemies = vec![body];
+
+        let player = player_at(vec2(100.0, 100.0));
+        assert!((enemies[0].pos - player.pos).length() < player.pickup_radius);
+        assert!(player
+            .body_in_reach(&enemies, &pathfinder_over(&[wall]))
+            .is_none());
+        assert!(player
+            .body_in_reach(&enemies, &pathfinder_over(&[]))
+            .is_some());
+
+        // a guard on the player's side looking straight at the wall
+        let mut guard = enemy_at(vec2(60.0, 100.0));
+        guard.facing = 0.0;
+        let away = player_at(vec2(60.0, 400.0));
+        let bodies = [enemies[0].detection_rect()];
+        let segments = rects_to_segments(&[wall]).collect::<Vec<_>>();
+        assert_eq!(guard.get_suspicion(&segments, &bodies, &away), None);
+        assert!(guard.get_suspicion(&[], &bodies, &away).is_some());
+    }
+
+    #[test]
+    fn guards_see_the_edges_of_the_sprite_as_drawn() {
+        let player = player_at(vec2(200.0, 100.0));
+        let size = player.curr_sprite().size();
+        let top_left = player.pos - size / 2.0 - vec2(0.0, SPRITE_Y_OFFSET);
+        let drawn = Rect::new(top_left.x, top_left.y, size.x, size.y);
+        assert_eq!(player.detection_rect(), drawn);
+
+        // a ledge hides all but the top two rows of the sprite from the guard
+        let top = drawn.y;
+        let ledge = [(vec2(20.0, top + 2.0), vec2(300.0, top + 2.0))];
+        let mut guard = enemy_at(vec2(0.0, top - 20.0));
+        guard.facing = 0.0;
+        assert_eq!(
+            guard.get_suspicion(&ledge, &[], &player),
+            Some((drawn.center(), true))
+        );
+        assert!(!guard.is_rect_in_cone(player.collision_rect()));
+    }
+
+    #[test]
+    fn guard_spawned_on_a_collider_edge_is_nudged_out_and_can_path() {
+        let wall = Rect::new(200.0, 200.0, 100.0, 100.0);
+        let pathfinder = pathfinder_over(&[wall]);
+        let mut guard = enemy_at(vec2(200.0, 250.0));
+        assert!(pathfinder.is_rect_colliding(guard.collision_rect()));
+
+        guard.move_to_first_node(&pathfinder);
+        assert!(!pathfinder.is_rect_colliding(guard.collision_rect()));
+        // straight out of the near side, not around to another one
+        assert!(guard.pos.x < wall.x && (guard.pos.y - 250.0).abs() < 8.0);
+
+        let to = vec2(100.0, 250.0);
+        let path = pathfinder.get_path(guard.collision_rect(), to).unwrap();
+        assert!(path.last().unwrap().distance(to) < 8.0);
+    }
+
+    #[test]
+    fn blocked_target_falls_back_right_then_left_then_down_then_up() {
+        // the walker is small enough to fit in the cell next to a blocked one
+        let walker = Rect::new(19.0, 99.0, 2.0, 2.0);
+        let target = vec2(100.0, 100.0);
+        let cell = |x: f32, y: f32| Rect::new(x - 4.0, y - 4.0, 8.0, 8.0);
+        let mut blocked = vec![cell(100.0, 100.0)];
+        for expected in [
+            vec2(108.0, 100.0),
+            vec2(92.0, 100.0),
+            vec2(100.0, 108.0),
+            vec2(100.0, 92.0),
+        ] {
+            let pathfinder = pathfinder_over(&blocked);
+            let path = pathfinder.get_path(walker, target).unwrap();
+            assert_eq!(*path.last().unwrap(), expected);
+            blocked.push(cell(expected.x, expected.y));
+        }
+        assert!(pathfinder_over(&blocked).get_path(walker, target).is_none());
+    }
+
+    // the path an arrow shot from the origin at 45 degrees takes, checked to be finite and never
+    // to end up inside a collider
+    fn shot_down_the_diagonal(rects: Vec<Rect>) -> Vec<Intersection> {
+        let colliders = Colliders::new(rects);
+        let path = get_intersections(Vec2::ZERO, vec2(1.0, 1.0).normalize(), &[], &colliders);
+        for int in path.iter() {
+            assert!(int.pos.is_finite() && int.normal.is_finite() && int.new_dir.is_finite());
+            let inside = |r: &Rect| {
+                int.pos.x > r.left() + 0.5
+                    && int.pos.x < r.right() - 0.5
+                    && int.pos.y > r.top() + 0.5
+                    && int.pos.y < r.bottom() - 0.5
+            };
+            assert!(!colliders.iter().any(inside), "{:?} tunneled", int.pos);
+        }
+        path
+    }
+
+    #[test]
+    fn shot_into_an_inside_corner_comes_straight_back() {
+        // an L of two walls whose inside corner is at (100, 100)
+        let path = shot_down_the_diagonal(vec![
+            Rect::new(100.0, 0.0, 20.0, 120.0),
+            Rect::new(0.0, 100.0, 120.0, 20.0),
+        ]);
+        assert!(matches!(path[0].entity, IsectType::Collider(_)));
+        assert!(path[0].pos.distance(vec2(100.0, 100.0)) < 0.01);
+        assert!(path[0].new_dir.distance(-vec2(1.0, 1.0).normalize()) < 0.01);
+        assert!(matches!(path[1].entity, IsectType::Air));
+    }
+
+    #[test]
+    fn shot_into_an_outside_corner_comes_straight_back() {
+        let path = shot_down_the_diagonal(vec![Rect::new(100.0, 100.0, 50.0, 50.0)]);
+        assert!(matches!(path[0].entity, IsectType::Collider(0)));
+        assert!(path[0].pos.distance(vec2(100.0, 100.0)) < 0.01);
+        assert!(path[0].new_dir.distance(-vec2(1.0, 1.0).normalize()) < 0.01);
+        assert!(matches!(path[1].entity, IsectType::Air));
+    }
+
+    #[test]
+    fn caught_player_sits_facing_the_last_side_they_moved_to() {
+        let mut player = player_at(vec2(0.0, 0.0));
+        player.state = PlayerState::Caught;
+        // moving straight up or down keeps the side from before
+        for (v, sit) in [
+            (vec2(1.0, 0.0), "sit_right"),
+            (vec2(0.0, -1.0), "sit_right"),
+            (vec2(0.0, 1.0), "sit_right"),
+            (vec2(-1.0, 0.0), "sit_left"),
+            (vec2(0.0, -1.0), "sit_left"),
+            (vec2(0.0, 1.0), "sit_left"),
+            (vec2(1.0, 1.0), "sit_right"),
+        ] {
+            player.go(v, &[]);
+            assert!(
+                std::ptr::eq(player.curr_sprite(), player.smap.get(sit)),
+                "{v}"
+            );
+        }
+    }
+
+    // whether segments a and b cross at a point inside both, rather than just touching
+    fn properly_cross(a: (Vec2, Vec2), b: (Vec2, Vec2)) -> bool {
+        let side = |p: Vec2, q: Vec2, r: Vec2| {
+            let c = (q - p).perp_dot(r - p);
+            if c.abs() < 1e-3 {
+                0.0
+            } else {
+                c.signum()
+            }
+        };
+        side(a.0, a.1, b.0) * side(a.0, a.1, b.1) < 0.0
+            && side(b.0, b.1, a.0) * side(b.0, b.1, a.1) < 0.0
+    }
+
+    #[test]
+    fn shadow_polygon_around_an_l_shaped_occluder_does_not_fold() {
+        // an L of two merged tile rects inside the level bounds, lit from all around it, on a grid
+        // that lines up with its edges and corners
+        let l = [
+            Rect::new(100.0, 100.0, 32.0, 128.0),
+            Rect::new(132.0, 196.0, 96.0, 32.0),
+        ];
+        let segments = rects_to_segments(&[l[0], l[1], BOUNDS]).collect::<Vec<_>>();
+        let spots = (-8..40)
+            .flat_map(|x| (-8..40).map(move |y| vec2(x as f32, y as f32) * 8.0 + 4.0))
+            // a light right on the wall has no sensible shadow, and guards never get that close
+            .filter(|p| {
+                !l.iter()
+                    .any(|r| Rect::new(r.x - 1.0, r.y - 1.0, r.w + 2.0, r.h + 2.0).contains(*p))
+            });
+        for pos in spots {
+            let polygon = bench::shadow_polygon(pos, &segments, 2000.0);
+            // every triangle of the fan winds the same way round the light, give or take rounding
+            let area = |t: &[u16]| {
+                let p = |i: u16| polygon.vertices[i as usize].position.xy();
+                let (a, b) = (p(t[1]) - p(t[0]), p(t[2]) - p(t[0]));
+                a.perp_dot(b) / (a.length() * b.length()).max(1.0)
+            };
+            let wind = polygon
+                .indices
+                .chunks_exact(3)
+                .map(area)
+                .sum::<f32>()
+                .signum();
+            for t in polygon.indices.chunks_exact(3) {
+                assert!(area(t) * wind > -1e-5, "folded fan from {pos}");
+            }
+
+            // and the outline it traces never crosses itself
+            let mut outline = polygon.vertices[1..]
+                .iter()
+                .map(|v| v.position.xy())
+                .collect::<Vec<_>>();
+            outline.dedup_by(|a, b| a.distance(*b) < 1e-3);
+            let edges = (0..outline.len())
+                .map(|i| (outline[i], outline[(i + 1) % outline.len()]))
+                .collect::<Vec<_>>();
+            for i in 0..edges.len() {
+                for j in i + 2..edges.len() {
+                    if i == 0 && j == edges.len() - 1 {
+                        continue;
+                    }
+                    assert!(
+                        !properly_cross(edges[i], edges[j]),
+                        "outline from {pos} crosses itself at {:?} and {:?}",
+                        edges[i],
+                        edges[j]
+                    );
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn arrow_leaving_through_a_gap_in_the_edge_wall_is_cleaned_up_at_the_bounds() {
+        // the wall along the right edge has a doorway, and the level has nothing past it
+        let colliders = Colliders::new(vec![
+            Rect::new(480.0, -512.0, 32.0, 496.0),
+            Rect::new(480.0, 16.0, 32.0, 496.0),
+        ]);
+        let mut enemies = Vec::new();
+        let path = get_intersections(vec2(0.0, 0.0), vec2(1.0, 0.0), &enemies, &colliders);
+        assert!(matches!(
+            path[..],
+            [Intersection {
+                entity: IsectType::Air,
+                ..
+            }]
+        ));
+        let mut arrow = Projectile::new(vec2(0.0, 0.0), &path, ArrowType::Lethal);
+
+        let dt = 1.0 / 60.0;
+        let mut last = arrow.pos;
+        while !arrow.tick(BOUNDS, &colliders, &mut enemies, dt) {
+            assert!(BOUNDS.contains(arrow.pos), "{} is off the map", arrow.pos);
+            last = arrow.pos;
+        }
+        // gone on the first tick past the edge, well before the end of its path
+        assert!(last.x > BOUNDS.right() - 500.0 * dt);
+        assert!(arrow.pos.x < path[0].pos.x);
+    }
+
+    #[test]
+    fn body_dropped_against_a_wall_lands_in_open_space() {
+        let wall = Rect::new(200.0, 40.0, 64.0, 200.0);
+        let pathfinder = pathfinder_over(&[wall]);
+        let mut open = enemy_at(vec2(150.0, 100.0));
+        let mut pressed = enemy_at(vec2(204.0, 100.0));
+        open.die();
+        pressed.die();
+        let mut enemies = vec![open, pressed];
+        assert!(pathfinder.is_rect_colliding(enemies[1].collision_rect()));
+
+        let mut player = player_at(vec2(180.0, 100.0));
+        for i in 0..enemies.len() {
+            let before = enemies[i].pos;
+            player.carrying = Some((enemies[i].id, before - player.pos));
+            player.drop_body(&mut enemies, &pathfinder);
+            assert!(player.carrying.is_none());
+
+            let body = &enemies[i];
+            assert!(!pathfinder.is_rect_colliding(body.collision_rect()));
+            // out of the wall on the side it was dropped from, and no further than it had to go
+            assert!(body.pos.x < wall.x && body.pos.distance(before) < 24.0);
+            assert!(i == 1 || body.pos == before);
+        }
+        assert!(player.body_in_reach(&enemies[1..], &pathfinder).is_some());
+    }
+
+    #[test]
+    fn closing_and_opening_a_door_re_paths() {
+        // a wall down the middle of the level with a doorway in it
+        let door = Rect::new(240.0, 240.0, 32.0, 32.0);
+        let mut pathfinder = pathfinder_over(&[
+            Rect::new(240.0, 0.0, 32.0, 240.0),
+            Rect::new(240.0, 272.0, 32.0, 240.0),
+        ]);
+        let walker = Rect::new(98.0, 254.0, 4.0, 4.0);
+        let to = vec2(400.0, 256.0);
+        let open = pathfinder.get_path(walker, to).unwrap();
+
+        pathfinder.set_rect(door, true);
+        assert!(pathfinder.is_rect_colliding(door));
+        assert!(pathfinder.get_path(walker, to).is_none());
+        pathfinder.set_rect(door, false);
+        assert_eq!(pathfinder.get_path(walker, to), Some(open.clone()));
+
+        // a single cell in the doorway only narrows it
+        let cell = pathfinder.vec2_to_cell(door.center());
+        pathfinder.set_cell(cell.x, cell.y, true);
+        assert!(pathfinder.is_collider(cell.x, cell.y));
+        let narrowed = pathfinder.get_path(walker, to).unwrap();
+        assert_ne!(narrowed, open);
+        assert_eq!(narrowed.last(), open.last());
+        let blocked = pathfinder.cell_to_vec2(cell);
+        for (a, b) in narrowed.iter().zip(narrowed.iter().skip(1)) {
+            let rect = Rect::new(a.x - 2.0, a.y - 2.0, 4.0, 4.0);
+            assert!(
+                !pathfinder.is_direct_path_blocked(rect, *b),
+                "{a} to {b} crosses {blocked}"
+            );
+        }
+        pathfinder.set_cell(cell.x, cell.y, false);
+        assert_eq!(pathfinder.get_path(walker, to), Some(open));
+    }
+
+    #[test]
+    fn one_node_sentry_stands_watch_and_scans() {
+        let half_arc = TAU / 8.0;
+        let path = ldtk::tests::one_node_patrol(&[
+            ("WaitTiming", serde_json::json!([])),
+            ("WalkTiming", serde_json::json!([])),
+            ("ScanArc", serde_json::json!(90.0)),
+        ]);
+        let mut sentry = Enemy::new(path, sprite_map(char_anims()), emotes());
+        let pathfinder = pathfinder_over(&[]);
+        // well past the detection radius
+        let mut player = player_at(vec2(100.0, 800.0));
+
+        let (mut least, mut most) = (0.0f32, 0.0f32);
+        for _ in 0..(2.0 * SCAN_PERIOD * 60.0) as usize {
+            sentry.tick(&pathfinder, &[], &[], &[], &mut player, 1.0 / 60.0);
+            assert!(matches!(sentry.state, EnemyState::Patrolling));
+            assert_eq!(
+                (sentry.pos, sentry.patrol_path.curr),
+                (vec2(100.0, 100.0), 0)
+            );
+            assert!(!sentry.is_moving);
+            let facing = (sentry.facing + TAU / 2.0).rem_euclid(TAU) - TAU / 2.0;
+            least = least.min(facing);
+            most = most.max(facing);
+        }
+        // sweeps the whole arc either side of the node's facing, and no further
+        assert!(least > -half_arc - 1e-3 && least < -half_arc + 0.01);
+        assert!(most < half_arc + 1e-3 && most > half_arc - 0.01);
+    }
+
+    #[test]
+    fn guard_assesses_a_still_player_and_reports_at_their_distance() {
+        let mut guard = enemy_at(vec2(100.0, 100.0));
+        guard.facing = 0.0;
+        let pathfinder = pathfinder_over(&[]);
+        let mut player = player_at(vec2(300.0, 100.0));
+        let dist = (player.detection_rect().center() - guard.pos).length();
+        let threshold = dist / guard.detection_radius;
+        let dt = 1.0 / 60.0;
+
+        guard.tick(&pathfinder, &[], &[], &[], &mut player, dt);
+        assert!(matches!(guard.state, EnemyState::Assessing(t) if t == 0.0));
+        let mut last = 0.0;
+        loop {
+            guard.tick(&pathfinder, &[], &[], &[], &mut player, dt);
+            assert_eq!(guard.pos, vec2(100.0, 100.0));
+            match guard.state {
+                EnemyState::Assessing(t) => {
+                    assert!((t - (last + dt / ASSESS_DURATION)).abs() < 1e-5);
+                    assert!(t < threshold);
+                    last = t;
+                }
+                EnemyState::Reporting(is_player) => {
+                    assert!(is_player);
+                    break;
+                }
+                _ => panic!("the guard stopped assessing"),
+            }
+        }
+        // the report comes on the step that would have carried t past the player's distance
+        assert!(last + dt / ASSESS_DURATION >= threshold);
+        assert!(last > threshold - 2.0 * dt / ASSESS_DURATION);
+    }
+
+    #[test]
+    fn guard_remembers_report_contact_until_it_gives_up() {
+        let mut guard = enemy_at(vec2(100.0, 100.0));
+        guard.facing = 0.0;
+        guard.state = EnemyState::Reporting(true);
+        let pathfinder = pathfinder_over(&[]);
+        let mut player = player_at(vec2(200.0, 100.0));
+        let dt = 1.0 / 60.0;
+        for _ in 0..6 {
+            guard.tick(&pathfinder, &[], &[], &[], &mut player, dt);
+        }
+        let contact = guard.report_contact;
+        assert!((contact - 6.0 * dt).abs() < 1e-5);
+
+        // ducking out of sight and straight back in picks the report up where it left off
+        let seen_at = player.pos;
+        player.pos = vec2(100.0, 800.0);
+        guard.tick(&pathfinder, &[], &[], &[], &mut player, dt);
+        assert!(matches!(guard.state, EnemyState::Searching(_)));
+        player.pos = seen_at;
+        guard.tick(&pathfinder, &[], &[], &[], &mut player, dt);
+        assert!(matches!(guard.state, EnemyState::Reporting(true)));
+        assert_eq!(guard.report_contact, contact);
+
+        // staying hidden until the guard is back on patrol starts the next report from scratch
+        player.pos = vec2(100.0, 800.0);
+        guard.tick(&pathfinder, &[], &[], &[], &mut player, dt);
+        guard.state = EnemyState::Searching(dt / 1000.0);
+        guard.tick(&pathfinder, &[], &[], &[], &mut player, dt);
+        assert!(matches!(guard.state, EnemyState::Patrolling));
+        assert_eq!(guard.report_contact, 0.0);
+    }
+
+    #[test]
+    fn cone_is_recast_when_a_segment_is_swapped_for_another() {
+        let mut guard = enemy_at(vec2(0.0, 0.0));
+        guard.facing = 0.0;
+        let away = player_at(vec2(0.0, 2000.0));
+        let near = rects_to_segments(&[Rect::new(100.0, -20.0, 10.0, 40.0)]).collect::<Vec<_>>();
+        let far = rects_to_segments(&[Rect::new(300.0, -20.0, 10.0, 40.0)]).collect::<Vec<_>>();
+        assert_eq!(near.len(), far.len());
+
+        guard.get_suspicion(&near, &[], &away);
+        // standing still, so only the change of segments can throw the cone away
+        guard.get_suspicion(&far, &[], &away);
+        let (_, _, cached) = guard.cone.as_ref().unwrap();
+        let behind_near_wall = vec2(200.0, 0.0);
+        assert!(cached.contains(behind_near_wall));
+        assert!(!guard
+            .generate_cone(&near, WHITE, guard.detection_radius)
+            .contains(behind_near_wall));
+    }
+
+    #[test]
+    fn reused_cone_stays_within_reach_of_a_regenerated_one() {
+        let walls = [
+            Rect::new(200.0, -40.0, 20.0, 30.0),
+            Rect::new(250.0, 30.0, 40.0, 60.0),
+        ];
+        let segments = rects_to_segments(&walls).collect::<Vec<_>>();
+        let nearest_wall = 200.0;
+        let mut guard = enemy_at(vec2(0.0, 0.0));
+        guard.facing = 0.0;
+        let away = player_at(vec2(0.0, 2000.0));
+        guard.get_suspicion(&segments, &[], &away);
+
+        // drift and turn by just under what throws the cone away
+        guard.pos += vec2(0.3, 0.3);
+        guard.facing += CONE_REUSE_ANGLE * 0.9;
+        guard.get_suspicion(&segments, &[], &away);
+        let (cached_pos, _, cached) = guard.cone.as_ref().unwrap();
+        assert_eq!(*cached_pos, vec2(0.0, 0.0));
+        let fresh = guard.generate_cone(&segments, WHITE, guard.detection_radius);
+
+        // the cone's edge at the detection radius swings with the turn, and the far end of a
+        // shadow swings with the drift as much further out as the radius is past its wall, plus a
+        // pixel for where the samples fall
+        let eps = guard.detection_radius * CONE_REUSE_ANGLE
+            + CONE_REUSE_DISTANCE * guard.detection_radius / nearest_wall
+            + 1.0;
+        let p = |i: usize| fresh.vertices[i].position.xy();
+        let outline = (0..fresh.vertices.len())
+            .map(|i| (p(i), p((i + 1) % fresh.vertices.len())))
+            .collect::<Vec<_>>();
+        let mut inside = 0;
+        for y in (-380..380).step_by(5) {
+            for x in (-20..540).step_by(5) {
+                // off the whole numbers, so no sample lies right on a ray to a wall's corner,
+                // where neither triangle either side of it claims the point
+                let q = vec2(x as f32, y as f32) + 0.37;
+                if cached.contains(q) != fresh.contains(q) {
+                    let off = outline
+                        .iter()
+                        .map(|&(a, b)| segment_distance(q, a, b))
+                        .fold(f32::MAX, f32::min);
+                    assert!(off <= eps, "{q} is {off} from the regenerated cone");
+                }
+                inside += fresh.contains(q) as usize;
+            }
+        }
+        assert!(inside > 1000);
+    }
+
+    // rects scattered over a 1024px square, which the quadtrees split into QUADTREE_CELL cells
+    // lined up with the whole multiples of 64. about half the rects' edges sit right on those
+    // cell borders, the rest fall anywhere
+    fn scattered_rects(seed: u64) -> Vec<Rect> {
+        let mut state = seed;
+        let mut next = move |n: u64| {
+            // xorshift, so the rects don't depend on the game's random state
+            state ^= state << 13;
+            state ^= state >> 7;
+            state ^= state << 17;
+            state % n
+        };
+        // two specks in the corners pin the tree to exactly (0, 0) to (1024, 1024)
+        let mut rects = vec![
+            Rect::new(1.0, 1.0, 0.5, 0.5),
+            Rect::new(1022.5, 1022.5, 0.5, 0.5),
+        ];
+        for _ in 0..40 {
+            let mut coord = |max: u64| {
+                if next(2) == 0 {
+                    (next(max / 64) * 64) as f32
+                } else {
+                    next(max * 8) as f32 / 8.0
+                }
+            };
+            let (x, y) = (coord(768) + 64.0, coord(768) + 64.0);
+            let (w, h) = (coord(128).max(8.0), coord(128).max(8.0));
+            rects.push(Rect::new(x, y, w, h));
+        }
+        rects
+    }
+
+    #[test]
+    fn quadtree_narrowing_matches_a_full_scan() {
+        for seed in 1..=8 {
+            let rects = scattered_rects(seed);
+            let colliders = Colliders::new(rects.clone());
+            // a tree with a single cell holding every edge is the same as scanning all of them
+            let mut all = Colliders::new(rects.clone());
+            all.edges = QuadTree::build_around(
+                vec2(4096.0, 4096.0),
+                &rects_to_segments(&rects).collect::<Vec<_>>(),
+            );
+
+            // shots from every 64th pixel along the borders, and from in between
+            for i in 0..=32 {
+                let start = vec2(16.0 + i as f32 * 31.0, (i % 16) as f32 * 64.0);
+                for dir in [
+                    vec2(1.0, 0.0),
+                    vec2(-1.0, 0.0),
+                    vec2(0.0, 1.0),
+                    vec2(1.0, 1.0).normalize(),
+                    vec2(-3.0, 1.0).normalize(),
+                    vec2(0.2, -1.0).normalize(),
+                ] {
+                    assert_eq!(
+                        format!("{:?}", get_intersections(start, dir, &[], &colliders)),
+                        format!("{:?}", get_intersections(start, dir, &[], &all)),
+                        "seed {seed} from {start} towards {dir}"
+                    );
+                }
+            }
+
+            let segments = rects_to_segments(&rects).collect::<Vec<_>>();
+            let shadow_tree = QuadTree::build_around(QUADTREE_CELL, &segments);
+            for i in 0..=32 {
+                let pos = vec2((i % 16) as f32 * 64.0, 20.0 + i as f32 * 29.0);
+                let radius = 100.0 + i as f32 * 10.0;
+                let nearby = shadow_tree.filter_by_radius(pos, radius);
+                for &(a, b) in segments.iter() {
+                    if segment_distance(pos, a, b) <= radius {
+                        assert!(nearby.contains(&(a, b)), "seed {seed} lost {a} to {b}");
+                    }
+                }
+                let facing = i as f32 * 0.7;
+                let cone = |segments: &[(Vec2, Vec2)]| {
+                    let cone = generate_view_cone(pos, facing, TAU / 4.0, segments, WHITE, radius);
+                    format!(
+                        "{:?}",
+                        cone.vertices.iter().map(|v| v.position).collect::<Vec<_>>()
+                    )
+                };
+                assert_eq!(cone(&nearby), cone(&segments), "seed {seed} at {pos}");
+            }
+        }
+    }
+}
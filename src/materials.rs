@@ -30,17 +30,25 @@ pub fn shadow_postprocess() -> Material {
 
         uniform sampler2D VisibleTexture;
         uniform sampler2D ConesTexture;
+        uniform sampler2D LightTexture;
+        uniform float LightFloor;
         uniform vec4 BgColor;
+        uniform vec4 AmbientColor;
+        uniform float DimFactor;
         uniform float AlarmTime;
         uniform float SwipeT;
+        uniform float SwipeDir;
+        uniform float VignetteStrength;
 
         void main() {
-            if (SwipeT > -1.0 && SwipeT < 0.0 && uv.x > -SwipeT) {
+            float swipeX = SwipeDir > 0.0 ? uv.x : 1.0 - uv.x;
+
+            if (SwipeT > -1.0 && SwipeT < 0.0 && swipeX > -SwipeT) {
                 gl_FragColor = vec4(0, 0, 0, 1);
                 return;
             }
 
-            if (SwipeT >= 0.0 && SwipeT <= 1.0 && uv.x <= 1.0 - SwipeT) {
+            if (SwipeT >= 0.0 && SwipeT <= 1.0 && swipeX <= 1.0 - SwipeT) {
                 gl_FragColor = vec4(0, 0, 0, 1);
                 return;
             }
@@ -49,15 +57,21 @@ pub fn shadow_postprocess() -> Material {
             vec4 cones = texture2D(ConesTexture, uv);
 
             if (cones.a == 0.0) {
-                vis = vis * 0.8;
+                vis = vis * DimFactor;
             } else {
-                if (cones.r > 0.0) {
+                // pure red is the assessing cone, anything else but black is a tinted cone
+                if (cones.r > 0.0 && cones.g == 0.0 && cones.b == 0.0) {
                     vis = mix(vis, vec4(1, 0, 0, 1), 0.5);
-                } else {
-                    vis = vis;
+                } else if (cones.r > 0.0 || cones.g > 0.0 || cones.b > 0.0) {
+                    vis = vec4(mix(vis.rgb, cones.rgb, 0.2), vis.a);
                 }
             }
 
+            vec4 light = texture2D(LightTexture, uv);
+            vis = vec4(vis.rgb * max(light.rgb, vec3(LightFloor)), vis.a);
+
+            vis = vec4(vis.rgb * AmbientColor.rgb, vis.a);
+
             float alarmIntensity = cos(5.0 * AlarmTime - 3.141592) + 1.0;
 
             if (vis.a == 0.0) {
@@ -65,6 +79,9 @@ pub fn shadow_postprocess() -> Material {
             } else {
                 gl_FragColor = mix(vis, vec4(1, 0, 0, 1), alarmIntensity * 0.1);
             }
+
+            float vignette = smoothstep(0.3, 0.75, length(uv - vec2(0.5)));
+            gl_FragColor.rgb *= 1.0 - VignetteStrength * vignette;
         }
     "#;
 
@@ -82,9 +99,18 @@ pub fn shadow_postprocess() -> Material {
             uniforms: vec![
                 UniformDesc::new("AlarmTime", UniformType::Float1),
                 UniformDesc::new("BgColor", UniformType::Float4),
+                UniformDesc::new("AmbientColor", UniformType::Float4),
+                UniformDesc::new("DimFactor", UniformType::Float1),
                 UniformDesc::new("SwipeT", UniformType::Float1),
+                UniformDesc::new("SwipeDir", UniformType::Float1),
+                UniformDesc::new("VignetteStrength", UniformType::Float1),
+                UniformDesc::new("LightFloor", UniformType::Float1),
+            ],
+            textures: vec![
+                "VisibleTexture".into(),
+                "ConesTexture".into(),
+                "LightTexture".into(),
             ],
-            textures: vec!["VisibleTexture".into(), "ConesTexture".into()],
             ..Default::default()
         },
     )
@@ -1,6 +1,6 @@
 use std::{
     cell::RefCell,
-    collections::{BinaryHeap, HashMap},
+    collections::{BinaryHeap, HashMap, HashSet},
     hash::Hash,
 };
 
@@ -8,26 +8,60 @@ use macroquad::prelude::*;
 
 use crate::debug_draw;
 
+/// The size and offset of the grid a level's walls are laid out on.
+#[derive(Debug, Clone, Copy)]
+pub struct Grid {
+    pub cell_size: Vec2,
+    pub offset: Vec2,
+}
+
+impl Default for Grid {
+    fn default() -> Self {
+        Self {
+            cell_size: vec2(8.0, 8.0),
+            offset: vec2(0.0, 0.0),
+        }
+    }
+}
+
 pub struct Pathfinder {
-    cells: Vec<bool>,
-    colliders_cache: Vec<IVec2>,
-    cell_size: f32,
+    // only the blocked cells are kept, since most of a big level is open floor
+    blocked: HashSet<IVec2>,
+    cell_size: Vec2,
+    // where cell (0, 0) starts, so cells line up with the level's walls
+    offset: Vec2,
     cells_width: i32,
     cells_height: i32,
+    // lets paths step to the 8 surrounding cells instead of only the 4 orthogonal ones
+    allow_diagonals: bool,
 }
 
+// step costs scaled by 10 so a diagonal is close to sqrt(2) without leaving integers
+const STRAIGHT_COST: i32 = 10;
+const DIAGONAL_COST: i32 = 14;
+
 impl Pathfinder {
-    pub fn new(level_width: f32, level_height: f32, colliders: &[Rect]) -> Self {
-        let mut cells = Vec::new();
-        let mut colliders_cache = Vec::new();
-        let cell_size = 8.0;
-        let cells_width = (level_width / cell_size) as i32;
-        let cells_height = (level_height / cell_size) as i32;
+    pub fn new(
+        level_width: f32,
+        level_height: f32,
+        grid: Grid,
+        colliders: &[Rect],
+        allow_diagonals: bool,
+    ) -> Self {
+        let mut blocked = HashSet::new();
+        // the level's grid split evenly into cells no bigger than 8px, so paths stay fine grained
+        let max_cell_size = 8.0;
+        let cell_size = grid.cell_size / (grid.cell_size / max_cell_size).ceil();
+        let offset = vec2(
+            grid.offset.x.rem_euclid(cell_size.x),
+            grid.offset.y.rem_euclid(cell_size.y),
+        );
+        let cells_width = ((level_width - offset.x) / cell_size.x) as i32;
+        let cells_height = ((level_height - offset.y) / cell_size.y) as i32;
 
         for y in 0..cells_height {
             for x in 0..cells_width {
-                let p = vec2(x as f32 * cell_size, y as f32 * cell_size)
-                    + vec2(cell_size / 2.0, cell_size / 2.0);
+                let p = offset + vec2(x as f32, y as f32) * cell_size + cell_size / 2.0;
                 // let rect = Rect::new(
                 //     x as f32 * cell_size,
                 //     y as f32 * cell_size,
@@ -35,20 +69,18 @@ impl Pathfinder {
                 //     cell_size,
                 // );
                 if colliders.iter().any(|c| c.contains(p)) {
-                    cells.push(true);
-                    colliders_cache.push(ivec2(x, y));
-                } else {
-                    cells.push(false);
+                    blocked.insert(ivec2(x, y));
                 }
             }
         }
 
         Self {
-            cells,
-            colliders_cache,
+            blocked,
             cell_size,
+            offset,
             cells_width,
             cells_height,
+            allow_diagonals,
         }
     }
 
@@ -58,31 +90,17 @@ impl Pathfinder {
             return None;
         }
 
-        let mut to_cell = self.vec2_to_cell(to);
-        let to_candidates = [
-            to_cell,
-            ivec2(to_cell.x + 1, to_cell.y),
-            ivec2(to_cell.x - 1, to_cell.y),
-            ivec2(to_cell.x, to_cell.y + 1),
-            ivec2(to_cell.x, to_cell.y - 1),
-        ];
+        let to_cell = self.walkable_cell_near(self.vec2_to_cell(to), rect.size(), 1)?;
 
-        if let Some(c) = to_candidates.iter().find(|&&p| {
-            let real_pos = self.cell_to_vec2(p);
-            let r = Rect::new(
-                real_pos.x - rect.w / 2.0,
-                real_pos.y - rect.h / 2.0,
-                rect.w,
-                rect.h,
-            );
-            !self.is_oob(p) && !self.is_rect_colliding(r)
-        }) {
-            to_cell = *c;
-        } else {
-            return None;
-        }
-
-        let h = |p: IVec2| (to_cell - p).length_squared();
+        let h = |p: IVec2| {
+            if self.allow_diagonals {
+                // octile distance, diagonals for the shorter axis and straight for the rest
+                let d = (to_cell - p).abs();
+                STRAIGHT_COST * (d.x + d.y) + (DIAGONAL_COST - 2 * STRAIGHT_COST) * d.x.min(d.y)
+            } else {
+                (to_cell - p).length_squared()
+            }
+        };
 
         let mut heap = BinaryHeap::<Node>::new();
         let mut g_scores = HashMap::<Node, i32>::new();
@@ -139,27 +157,14 @@ impl Pathfinder {
                 found = Some(curr);
                 break;
             }
-            let curr_real_pos = self.cell_to_vec2(curr.pos) - vec2(rect.w / 2.0, rect.h / 2.0);
-            let curr_rect = Rect {
-                x: curr_real_pos.x,
-                y: curr_real_pos.y,
-                ..rect
-            };
             for x in -1..=1 {
                 for y in -1..=1 {
                     let off = ivec2(x, y);
-                    let pos = curr.pos + off;
-                    if (off.x == 0 && off.y == 0) || off.x.abs() == off.y.abs() {
-                        continue;
-                    }
-                    if self.is_oob(pos) {
-                        continue;
-                    }
-
-                    let neib_real_pos = self.cell_to_vec2(pos);
-                    if self.is_direct_path_blocked(curr_rect, neib_real_pos) {
+                    if !self.can_step(rect, curr.pos, off) {
                         continue;
                     }
+                    let pos = curr.pos + off;
+                    let diagonal = off.x != 0 && off.y != 0;
 
                     let neib = Node {
                         pos,
@@ -174,7 +179,14 @@ impl Pathfinder {
                         });
                     }
 
-                    let neib_g = g_scores[&curr] + 1;
+                    let step_cost = if diagonal {
+                        DIAGONAL_COST
+                    } else if self.allow_diagonals {
+                        STRAIGHT_COST
+                    } else {
+                        1
+                    };
+                    let neib_g = g_scores[&curr] + step_cost;
                     if neib_g < *g_scores.get(&neib).unwrap_or(&i32::MAX) {
                         g_scores.insert(neib, neib_g);
                         f_scores.borrow_mut().insert(neib, neib_g + h(neib.pos));
@@ -216,6 +228,29 @@ impl Pathfinder {
         )
     }
 
+    // whether something the size of `rect` standing on `cell` can step to `cell + off`
+    fn can_step(&self, rect: Rect, cell: IVec2, off: IVec2) -> bool {
+        let diagonal = off.x != 0 && off.y != 0;
+        if off == IVec2::ZERO || (diagonal && !self.allow_diagonals) || self.is_oob(cell + off) {
+            return false;
+        }
+        let top_left = self.cell_to_vec2(cell) - rect.size() / 2.0;
+        let curr_rect = Rect {
+            x: top_left.x,
+            y: top_left.y,
+            ..rect
+        };
+        // no cutting corners, both cells beside a diagonal step have to be walkable
+        if diagonal
+            && [ivec2(off.x, 0), ivec2(0, off.y)]
+                .iter()
+                .any(|&side| self.is_direct_path_blocked(curr_rect, self.cell_to_vec2(cell + side)))
+        {
+            return false;
+        }
+        !self.is_direct_path_blocked(curr_rect, self.cell_to_vec2(cell + off))
+    }
+
     fn cleanup_path_redundancies(&self, rect: Rect, mut path: Vec<Vec2>) -> Vec<Vec2> {
         let mut new_path = Vec::new();
         let first = path.remove(0);
@@ -246,6 +281,37 @@ impl Pathfinder {
         new_path.into_iter().map(|r| r.center()).collect()
     }
 
+    // the center of the closest cell within `radius` cells of `pos` that a rect of `size` fits in
+    pub fn nearest_walkable(&self, pos: Vec2, size: Vec2, radius: i32) -> Option<Vec2> {
+        self.walkable_cell_near(self.vec2_to_cell(pos), size, radius)
+            .map(|c| self.cell_to_vec2(c))
+    }
+
+    fn walkable_cell_near(&self, cell: IVec2, size: Vec2, radius: i32) -> Option<IVec2> {
+        let manhattan = |p: IVec2| (p.x - cell.x).abs() + (p.y - cell.y).abs();
+        let mut candidates = (-radius..=radius)
+            .flat_map(|dy| (-radius..=radius).map(move |dx| ivec2(cell.x + dx, cell.y + dy)))
+            .filter(|&p| manhattan(p) <= radius)
+            .collect::<Vec<_>>();
+        // closest first, and at the same distance right, left, down then up, the order the
+        // neighbours of a blocked target have always been tried in
+        candidates.sort_by_key(|&p| {
+            let off = p - cell;
+            (manhattan(p), off.y.abs(), -off.x, -off.y)
+        });
+
+        candidates.into_iter().find(|&p| {
+            let real_pos = self.cell_to_vec2(p);
+            let r = Rect::new(
+                real_pos.x - size.x / 2.0,
+                real_pos.y - size.y / 2.0,
+                size.x,
+                size.y,
+            );
+            !self.is_oob(p) && !self.is_rect_colliding(r)
+        })
+    }
+
     pub fn is_direct_path_blocked(&self, mut rect: Rect, to: Vec2) -> bool {
         let orig_dist = to - rect.center();
         let dir = orig_dist.try_normalize();
@@ -269,22 +335,50 @@ impl Pathfinder {
     }
 
     pub fn is_rect_colliding(&self, rect: Rect) -> bool {
-        for IVec2 { x, y } in self.colliders_cache.iter() {
-            let cell = Rect::new(
-                *x as f32 * self.cell_size,
-                *y as f32 * self.cell_size,
-                self.cell_size,
-                self.cell_size,
-            );
-            if cell.overlaps(&rect) {
-                return true;
+        // overlapping counts touching edges, so look one cell further out on every side
+        let min = self.vec2_to_cell(rect.point()) - ivec2(1, 1);
+        let max = self.vec2_to_cell(rect.point() + rect.size()) + ivec2(1, 1);
+        for y in min.y..=max.y {
+            for x in min.x..=max.x {
+                if !self.blocked.contains(&ivec2(x, y)) {
+                    continue;
+                }
+                if self.cell_rect(ivec2(x, y)).overlaps(&rect) {
+                    return true;
+                }
             }
         }
         false
     }
 
+    // marks a cell as blocked or walkable, for colliders that change at runtime like doors.
+    // paths aren't cached, so anything asked for afterwards sees the change
+    pub fn set_cell(&mut self, x: i32, y: i32, blocked: bool) {
+        if self.is_oob(ivec2(x, y)) {
+            return;
+        }
+        if blocked {
+            self.blocked.insert(ivec2(x, y));
+        } else {
+            self.blocked.remove(&ivec2(x, y));
+        }
+    }
+
+    // sets every cell whose center is inside `rect`, the same test `new` uses for colliders
+    pub fn set_rect(&mut self, rect: Rect, blocked: bool) {
+        let min = self.vec2_to_cell(rect.point());
+        let max = self.vec2_to_cell(rect.point() + rect.size());
+        for y in min.y..=max.y {
+            for x in min.x..=max.x {
+                if rect.contains(self.cell_to_vec2(ivec2(x, y))) {
+                    self.set_cell(x, y, blocked);
+                }
+            }
+        }
+    }
+
     pub fn is_collider(&self, x: i32, y: i32) -> bool {
-        self.cells[(y * self.cells_width + x) as usize]
+        self.blocked.contains(&ivec2(x, y))
     }
 
     fn is_oob(&self, p: IVec2) -> bool {
@@ -292,10 +386,135 @@ impl Pathfinder {
     }
 
     pub fn vec2_to_cell(&self, v: Vec2) -> IVec2 {
-        (v / self.cell_size).as_ivec2()
+        ((v - self.offset) / self.cell_size).floor().as_ivec2()
+    }
+
+    fn cell_rect(&self, c: IVec2) -> Rect {
+        let p = self.offset + c.as_vec2() * self.cell_size;
+        Rect::new(p.x, p.y, self.cell_size.x, self.cell_size.y)
     }
 
     pub fn cell_to_vec2(&self, v: IVec2) -> Vec2 {
-        v.as_vec2() * self.cell_size + vec2(self.cell_size / 2.0, self.cell_size / 2.0)
+        self.offset + v.as_vec2() * self.cell_size + self.cell_size / 2.0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // a few rooms of walls that don't all line up with the cells
+    fn walls() -> Vec<Rect> {
+        vec![
+            Rect::new(0.0, 0.0, 256.0, 8.0),
+            Rect::new(0.0, 0.0, 8.0, 256.0),
+            Rect::new(96.0, 0.0, 12.0, 150.0),
+            Rect::new(96.0, 190.0, 12.0, 66.0),
+            Rect::new(150.0, 100.0, 61.0, 13.0),
+            Rect::new(30.0, 60.0, 33.0, 30.0),
+        ]
+    }
+
+    fn level(grid: Grid) -> Pathfinder {
+        Pathfinder::new(256.0, 256.0, grid, &walls(), false)
+    }
+
+    // goes over every blocked cell like the old dense grid did, to hold the lookup around a rect
+    // up against
+    fn scan_every_cell(pathfinder: &Pathfinder, rect: Rect) -> bool {
+        pathfinder
+            .blocked
+            .iter()
+            .any(|c| pathfinder.cell_rect(*c).overlaps(&rect))
+    }
+
+    const GRIDS: [Grid; 2] = [
+        Grid {
+            cell_size: vec2(8.0, 8.0),
+            offset: vec2(0.0, 0.0),
+        },
+        Grid {
+            cell_size: vec2(12.0, 16.0),
+            offset: vec2(5.0, 3.0),
+        },
+    ];
+
+    #[test]
+    fn sparse_cells_collide_like_the_dense_grid() {
+        for grid in GRIDS {
+            let pathfinder = level(grid);
+            for size in [vec2(0.5, 0.5), vec2(4.0, 4.0), vec2(16.0, 32.0)] {
+                for y in (-40..300).step_by(3) {
+                    for x in (-40..300).step_by(3) {
+                        let rect = Rect::new(x as f32, y as f32, size.x, size.y);
+                        assert_eq!(
+                            pathfinder.is_rect_colliding(rect),
+                            scan_every_cell(&pathfinder, rect),
+                            "{rect:?} on {grid:?}"
+                        );
+                    }
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn sparse_cells_give_paths_that_turn_clear_of_every_blocked_cell() {
+        let spots = [
+            vec2(50.0, 30.0),
+            vec2(50.0, 200.0),
+            vec2(180.0, 60.0),
+            vec2(200.0, 220.0),
+            vec2(130.0, 120.0),
+        ];
+        for grid in GRIDS {
+            let pathfinder = level(grid);
+            for size in [vec2(4.0, 4.0), vec2(16.0, 32.0)] {
+                let walker_at =
+                    |p: Vec2| Rect::new(p.x - size.x / 2.0, p.y - size.y / 2.0, size.x, size.y);
+                for from in spots {
+                    for to in spots {
+                        let path = pathfinder.get_path(walker_at(from), to);
+                        let Some(path) = path else {
+                            panic!("no way from {from} to {to} on {grid:?}");
+                        };
+                        // the walker fits at every turn along the way
+                        for &p in path.iter() {
+                            assert!(
+                                !scan_every_cell(&pathfinder, walker_at(p)),
+                                "from {from} to {to} turns at {p} on {grid:?}"
+                            );
+                        }
+                    }
+                }
+            }
+        }
+    }
+    #[test]
+    fn diagonal_steps_never_cut_a_wall_corner() {
+        let grid = GRIDS[0];
+        // small enough to slip past a corner without touching it
+        let walker = Rect::new(0.0, 0.0, 1.0, 1.0);
+        let cell = ivec2(4, 4);
+
+        // out in the open a diagonal step is taken, but only when diagonals are allowed
+        let open = Pathfinder::new(80.0, 80.0, grid, &[], true);
+        assert!(open.can_step(walker, cell, ivec2(1, 1)));
+        assert!(!Pathfinder::new(80.0, 80.0, grid, &[], false).can_step(walker, cell, ivec2(1, 1)));
+
+        // a wall on one side of the step refuses it, even though the walker would clear it
+        let corner = [Rect::new(40.0, 32.0, 8.0, 8.0)];
+        let past_corner = Pathfinder::new(80.0, 80.0, grid, &corner, true);
+        assert!(!past_corner.can_step(walker, cell, ivec2(1, 1)));
+        assert!(past_corner.can_step(walker, cell, ivec2(0, 1)));
+
+        // so a wall of cells that only meet at their corners can't be slipped through
+        let staircase = (0..10)
+            .map(|i| Rect::new(i as f32 * 8.0, (9 - i) as f32 * 8.0, 8.0, 8.0))
+            .collect::<Vec<_>>();
+        let sealed = Pathfinder::new(80.0, 80.0, grid, &staircase, true);
+        let from = Rect::new(12.0, 12.0, 1.0, 1.0);
+        assert_eq!(sealed.get_path(from, vec2(68.0, 68.0)), None);
+        assert!(open.get_path(from, vec2(68.0, 68.0)).is_some());
     }
 }
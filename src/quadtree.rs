@@ -4,37 +4,89 @@ use crate::geometry_utils::{intersect_lines, line_rect_intersect};
 
 type LineSeg = (Vec2, Vec2);
 
+// how far the cells reach past their edges, so a segment running along the border between two
+// cells, or a ray just grazing one, still ends up in both instead of slipping between them
+const CELL_MARGIN: f32 = 1.0;
+
 pub struct QuadTree {
     root: QuadNode,
+    segments: Vec<LineSeg>,
 }
 
 struct QuadNode {
     rect: Rect,
     children: Option<Vec<QuadNode>>,
-    segments: Option<Vec<LineSeg>>,
+    // indices into the tree's segments
+    segments: Option<Vec<usize>>,
+}
+
+fn grown(rect: Rect) -> Rect {
+    Rect::new(
+        rect.x - CELL_MARGIN,
+        rect.y - CELL_MARGIN,
+        rect.w + 2.0 * CELL_MARGIN,
+        rect.h + 2.0 * CELL_MARGIN,
+    )
+}
+
+fn touches(rect: Rect, line_segment: LineSeg) -> bool {
+    rect.contains(line_segment.0)
+        || rect.contains(line_segment.1)
+        || line_rect_intersect(line_segment.0, line_segment.1, rect).is_some()
 }
 
 impl QuadTree {
     pub fn build(rect: Rect, min_size: Vec2, line_segments: &[LineSeg]) -> Self {
+        let all = (0..line_segments.len()).collect::<Vec<_>>();
         Self {
-            root: QuadNode::build(rect, min_size, line_segments),
+            root: QuadNode::build(rect, min_size, line_segments, &all),
+            segments: line_segments.to_vec(),
         }
     }
 
+    // a tree just big enough to hold all of `line_segments`
+    pub fn build_around(min_size: Vec2, line_segments: &[LineSeg]) -> Self {
+        let (min, max) = line_segments.iter().fold(
+            (Vec2::splat(f32::MAX), Vec2::splat(f32::MIN)),
+            |(min, max), &(a, b)| (min.min(a).min(b), max.max(a).max(b)),
+        );
+        let rect = if line_segments.is_empty() {
+            Rect::default()
+        } else {
+            grown(Rect::new(min.x, min.y, max.x - min.x, max.y - min.y))
+        };
+        Self::build(rect, min_size, line_segments)
+    }
+
+    /// The indices of every segment that might cross `line_segment`, in the order they were built
+    /// with. Segments outside the tree's rect are never returned.
+    pub fn ids_by_segment(&self, line_segment: LineSeg) -> Vec<usize> {
+        self.ids(&|rect| touches(rect, line_segment))
+    }
+
     pub fn filter_by_segment(&self, line_segment: LineSeg) -> Vec<LineSeg> {
-        self.root.filter(&|rect| {
-            rect.contains(line_segment.0)
-                || rect.contains(line_segment.1)
-                || line_rect_intersect(line_segment.0, line_segment.1, rect).is_some()
-        })
+        self.lookup(self.ids_by_segment(line_segment))
     }
 
+    /// Every segment that might come within `radius` of `pos`, in the order they were built with.
     pub fn filter_by_radius(&self, pos: Vec2, radius: f32) -> Vec<LineSeg> {
-        self.root.filter(&|rect| {
-            let dist_to_center = (rect.center() - pos).length();
-            let dir = (rect.center() - pos).normalize_or_zero() * dist_to_center.min(radius);
-            rect.contains(pos + dir)
-        })
+        self.lookup(self.ids(&|rect| {
+            let closest = pos.clamp(rect.point(), rect.point() + rect.size());
+            closest.distance(pos) <= radius
+        }))
+    }
+
+    fn ids(&self, filter_fn: &impl Fn(Rect) -> bool) -> Vec<usize> {
+        let mut ids = Vec::new();
+        self.root.filter(filter_fn, &mut ids);
+        // a segment crossing several cells shows up once for each of them
+        ids.sort_unstable();
+        ids.dedup();
+        ids
+    }
+
+    fn lookup(&self, ids: Vec<usize>) -> Vec<LineSeg> {
+        ids.into_iter().map(|id| self.segments[id]).collect()
     }
 
     pub fn debug_draw(&self) {
@@ -43,7 +95,7 @@ impl QuadTree {
 
     pub fn intersect(&self, from: Vec2, to: Vec2) -> Option<Vec2> {
         if self.root.does_intersect(from, to) {
-            self.root.intersect(from, to)
+            self.root.intersect(from, to, &self.segments)
         } else {
             None
         }
@@ -73,7 +125,7 @@ impl QuadNode {
             || line_rect_intersect(from, to, self.rect).is_some()
     }
 
-    fn intersect(&self, from: Vec2, to: Vec2) -> Option<Vec2> {
+    fn intersect(&self, from: Vec2, to: Vec2, all: &[LineSeg]) -> Option<Vec2> {
         if let Some(children) = self.children.as_ref() {
             let mut children_ints = children
                 .iter()
@@ -97,12 +149,12 @@ impl QuadNode {
             let dir = (to - from).normalize() * 0.1;
             children_ints
                 .into_iter()
-                .map(|(c, i)| c.intersect(i + dir, to))
+                .map(|(c, i)| c.intersect(i + dir, to, all))
                 .find_map(|i| i)
         } else if let Some(segments) = self.segments.as_ref() {
             let mut ints = segments
                 .iter()
-                .filter_map(|li| intersect_lines(from, to, li.0, li.1))
+                .filter_map(|&id| intersect_lines(from, to, all[id].0, all[id].1))
                 .collect::<Vec<_>>();
             ints.sort_by(|&a, &b| {
                 (a - from)
@@ -120,65 +172,53 @@ impl QuadNode {
         }
     }
 
-    fn filter(&self, filter_fn: &impl Fn(Rect) -> bool) -> Vec<LineSeg> {
+    fn filter(&self, filter_fn: &impl Fn(Rect) -> bool, ids: &mut Vec<usize>) {
         assert!(self.children.is_some() || self.segments.is_some());
         assert!(!self.children.is_some() || !self.segments.is_some());
 
-        if !filter_fn(self.rect) {
-            return Vec::with_capacity(0);
+        if !filter_fn(grown(self.rect)) {
+            return;
         }
 
         if let Some(children) = self.children.as_ref() {
-            let mut ret = Vec::new();
             for child in children {
-                ret.extend(child.filter(filter_fn));
+                child.filter(filter_fn, ids);
             }
-            return ret;
+            return;
         }
 
         if let Some(segments) = self.segments.as_ref() {
-            return segments.clone();
+            ids.extend(segments.iter().copied());
+            return;
         }
 
         unreachable!()
     }
 
-    fn build(rect: Rect, min_size: Vec2, line_segments: &[LineSeg]) -> Self {
+    // `candidates` are the segments that touch the parent, so each level only tests those
+    fn build(rect: Rect, min_size: Vec2, line_segments: &[LineSeg], candidates: &[usize]) -> Self {
+        let inside = candidates
+            .iter()
+            .copied()
+            .filter(|&id| touches(grown(rect), line_segments[id]))
+            .collect::<Vec<_>>();
         let (segments, children) = if rect.size().length_squared() <= min_size.length_squared() {
-            (
-                Some(
-                    line_segments
-                        .iter()
-                        .filter(|li| {
-                            rect.contains(li.0)
-                                || rect.contains(li.1)
-                                || line_rect_intersect(li.0, li.1, rect).is_some()
-                        })
-                        .map(|li| *li)
-                        .collect::<Vec<_>>(),
-                ),
-                None,
-            )
+            (Some(inside), None)
         } else {
             (
                 None,
                 Some(
                     (0..=1)
                         .map(|h| {
-                            (0..=1).map(move |v| {
-                                QuadNode::build(
-                                    Rect {
-                                        x: rect.x + h as f32 * rect.w / 2.0,
-                                        y: rect.y + v as f32 * rect.h / 2.0,
-                                        w: rect.w / 2.0,
-                                        h: rect.h / 2.0,
-                                    },
-                                    min_size,
-                                    line_segments,
-                                )
+                            (0..=1).map(move |v| Rect {
+                                x: rect.x + h as f32 * rect.w / 2.0,
+                                y: rect.y + v as f32 * rect.h / 2.0,
+                                w: rect.w / 2.0,
+                                h: rect.h / 2.0,
                             })
                         })
                         .flatten()
+                        .map(|child| QuadNode::build(child, min_size, line_segments, &inside))
                         .collect::<Vec<_>>(),
                 ),
             )
@@ -0,0 +1,213 @@
+use std::sync::Mutex;
+
+use lazy_static::lazy_static;
+use macroquad::{input, prelude::*};
+use serde::{Deserialize, Serialize};
+
+// a debug recording of a run for bug reports: the seed the level was loaded with and every
+// frame's input. starting the game with `--replay <file>` plays one back, which the game picks
+// up by reading its input and frame time through the functions below instead of macroquad's.
+// the mouse is kept in pixels, so a replay only matches in a window of the same size
+
+#[derive(Serialize, Deserialize)]
+struct InputFrame {
+    // the frame time before it is clamped or slowed down
+    dt: f32,
+    keys_down: Vec<String>,
+    mouse_pos: (f32, f32),
+    mouse_down: Vec<String>,
+    #[serde(default)]
+    mouse_wheel: (f32, f32),
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct Recording {
+    seed: u64,
+    level: String,
+    frames: Vec<InputFrame>,
+}
+
+struct Playback {
+    frames: Vec<InputFrame>,
+    // one past the frame being played, whose input is compared with the frame before it to
+    // tell what was just pressed or released
+    next: usize,
+}
+
+impl Playback {
+    fn frame(&self) -> &InputFrame {
+        &self.frames[self.next.saturating_sub(1)]
+    }
+
+    fn last_frame(&self) -> Option<&InputFrame> {
+        self.next.checked_sub(2).map(|i| &self.frames[i])
+    }
+}
+
+lazy_static! {
+    static ref PLAYBACK: Mutex<Option<Playback>> = Mutex::new(None);
+}
+
+impl Recording {
+    pub fn new(seed: u64, level: &str) -> Self {
+        Self {
+            seed,
+            level: level.to_string(),
+            frames: Vec::new(),
+        }
+    }
+
+    pub fn record_frame(&mut self, dt: f32) {
+        // sorted so the same input always writes the same frame
+        let mut keys_down = get_keys_down()
+            .into_iter()
+            .map(|k| format!("{k:?}"))
+            .collect::<Vec<_>>();
+        keys_down.sort();
+        let mouse_down = [MouseButton::Left, MouseButton::Right, MouseButton::Middle]
+            .into_iter()
+            .filter(|&b| input::is_mouse_button_down(b))
+            .map(|b| format!("{b:?}"))
+            .collect();
+        self.frames.push(InputFrame {
+            dt,
+            keys_down,
+            mouse_pos: input::mouse_position(),
+            mouse_down,
+            mouse_wheel: input::mouse_wheel(),
+        });
+    }
+
+    #[cfg(not(target_arch = "wasm32"))]
+    pub fn save(&self) {
+        let path = format!("replay-{}.json", self.seed);
+        match std::fs::write(&path, serde_json::to_string(self).unwrap()) {
+            Ok(()) => eprintln!("wrote {} frames to {path}", self.frames.len()),
+            Err(e) => eprintln!("could not write {path}: {e}"),
+        }
+    }
+
+    #[cfg(target_arch = "wasm32")]
+    pub fn save(&self) {
+        eprintln!("replays can't be saved on web");
+    }
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+pub fn replay_path() -> Option<String> {
+    let mut args = std::env::args().skip_while(|arg| arg != "--replay");
+    args.next();
+    args.next()
+}
+
+#[cfg(target_arch = "wasm32")]
+pub fn replay_path() -> Option<String> {
+    None
+}
+
+/// Loads a recording to play back, and returns the seed and level it has to start from.
+pub fn start_playback(path: &str) -> Option<(u64, String)> {
+    let recording = std::fs::read_to_string(path)
+        .map_err(|e| e.to_string())
+        .and_then(|s| serde_json::from_str::<Recording>(&s).map_err(|e| e.to_string()));
+    match recording {
+        Ok(recording) if !recording.frames.is_empty() => {
+            eprintln!("playing back {} frames from {path}", recording.frames.len());
+            *PLAYBACK.lock().unwrap() = Some(Playback {
+                frames: recording.frames,
+                next: 0,
+            });
+            Some((recording.seed, recording.level))
+        }
+        Ok(_) => {
+            eprintln!("{path} has no frames to play back");
+            None
+        }
+        Err(e) => {
+            eprintln!("could not read {path}: {e}");
+            None
+        }
+    }
+}
+
+/// Moves playback on to the next frame and returns its frame time, or None when nothing is
+/// being played back. Call this once at the start of every frame.
+pub fn advance_playback() -> Option<f32> {
+    let mut playback = PLAYBACK.lock().unwrap();
+    let p = playback.as_mut()?;
+    if p.next == p.frames.len() {
+        eprintln!("playback finished");
+        *playback = None;
+        return None;
+    }
+    p.next += 1;
+    Some(p.frame().dt)
+}
+
+// whether a key, or a mouse button, is held in the frame being played back and in the one
+// before it, or None when nothing is being played back
+fn played_back(name: String, mouse: bool) -> Option<(bool, bool)> {
+    let playback = PLAYBACK.lock().unwrap();
+    let p = playback.as_ref()?;
+    let held = |f: &InputFrame| {
+        let held = if mouse { &f.mouse_down } else { &f.keys_down };
+        held.contains(&name)
+    };
+    Some((held(p.frame()), p.last_frame().is_some_and(held)))
+}
+
+pub fn is_key_down(key: KeyCode) -> bool {
+    match played_back(format!("{key:?}"), false) {
+        Some((now, _)) => now,
+        None => input::is_key_down(key),
+    }
+}
+
+pub fn is_key_pressed(key: KeyCode) -> bool {
+    match played_back(format!("{key:?}"), false) {
+        Some((now, before)) => now && !before,
+        None => input::is_key_pressed(key),
+    }
+}
+
+pub fn is_key_released(key: KeyCode) -> bool {
+    match played_back(format!("{key:?}"), false) {
+        Some((now, before)) => !now && before,
+        None => input::is_key_released(key),
+    }
+}
+
+pub fn is_mouse_button_down(button: MouseButton) -> bool {
+    match played_back(format!("{button:?}"), true) {
+        Some((now, _)) => now,
+        None => input::is_mouse_button_down(button),
+    }
+}
+
+pub fn is_mouse_button_pressed(button: MouseButton) -> bool {
+    match played_back(format!("{button:?}"), true) {
+        Some((now, before)) => now && !before,
+        None => input::is_mouse_button_pressed(button),
+    }
+}
+
+pub fn is_mouse_button_released(button: MouseButton) -> bool {
+    match played_back(format!("{button:?}"), true) {
+        Some((now, before)) => !now && before,
+        None => input::is_mouse_button_released(button),
+    }
+}
+
+pub fn mouse_position() -> (f32, f32) {
+    match PLAYBACK.lock().unwrap().as_ref() {
+        Some(p) => p.frame().mouse_pos,
+        None => input::mouse_position(),
+    }
+}
+
+pub fn mouse_wheel() -> (f32, f32) {
+    match PLAYBACK.lock().unwrap().as_ref() {
+        Some(p) => p.frame().mouse_wheel,
+        None => input::mouse_wheel(),
+    }
+}
@@ -0,0 +1,172 @@
+use std::{
+    collections::{HashMap, HashSet},
+    sync::{Mutex, MutexGuard},
+};
+
+use lazy_static::lazy_static;
+use serde::{Deserialize, Serialize};
+
+use crate::settings::Settings;
+
+#[cfg(not(target_arch = "wasm32"))]
+const DEFAULT_SAVE_PATH: &str = "save.json";
+// overrides where the save is kept, so test runs don't touch the real one
+#[cfg(not(target_arch = "wasm32"))]
+const SAVE_PATH_VAR: &str = "MUSEUM_ASSASSIN_SAVE";
+
+#[cfg(not(target_arch = "wasm32"))]
+fn save_path() -> String {
+    std::env::var(SAVE_PATH_VAR).unwrap_or_else(|_| DEFAULT_SAVE_PATH.to_string())
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+fn read_save() -> Option<String> {
+    std::fs::read_to_string(save_path()).ok()
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+fn write_save(contents: &str) {
+    let path = save_path();
+    if let Err(e) = std::fs::write(&path, contents) {
+        eprintln!("could not write {path}: {e}");
+    }
+}
+
+// on web the save lives in localStorage, through the plugin in web/storage.js
+#[cfg(target_arch = "wasm32")]
+mod storage {
+    use sapp_jsutils::JsObject;
+
+    extern "C" {
+        fn museum_assassin_storage_get(key: JsObject) -> JsObject;
+        fn museum_assassin_storage_set(key: JsObject, value: JsObject);
+    }
+
+    const STORAGE_KEY: &str = "museum_assassin_save";
+
+    pub fn read_save() -> Option<String> {
+        let value = unsafe { museum_assassin_storage_get(JsObject::string(STORAGE_KEY)) };
+        if value.is_nil() {
+            return None;
+        }
+        let mut contents = String::new();
+        value.to_string(&mut contents);
+        Some(contents)
+    }
+
+    pub fn write_save(contents: &str) {
+        unsafe {
+            museum_assassin_storage_set(JsObject::string(STORAGE_KEY), JsObject::string(contents));
+        }
+    }
+}
+
+#[cfg(target_arch = "wasm32")]
+use storage::{read_save, write_save};
+
+lazy_static! {
+    static ref SAVE: Mutex<SaveData> = Mutex::new(SaveData::load());
+}
+
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Debug)]
+pub enum Medal {
+    Bronze,
+    Silver,
+    Gold,
+}
+
+impl Medal {
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::Bronze => "Bronze",
+            Self::Silver => "Silver",
+            Self::Gold => "Gold",
+        }
+    }
+}
+
+// fields missing from older saves start out empty
+#[derive(Serialize, Deserialize, Default)]
+#[serde(default)]
+pub struct SaveData {
+    // the best medal earned on each level, by level name
+    pub medals: HashMap<String, Medal>,
+    // every level that has been cleared at least once
+    pub cleared_levels: HashSet<String>,
+    // lifetime totals for the stats screen, playtime in seconds
+    pub playtime: f64,
+    pub kills: u32,
+    pub runs: u32,
+    pub detections: u32,
+    // the options as they were last left
+    pub settings: Settings,
+}
+
+impl SaveData {
+    // a missing save just starts over, and so does a corrupt one, after a warning
+    fn load() -> Self {
+        match read_save().map(|s| serde_json::from_str(&s)) {
+            Some(Ok(save)) => save,
+            Some(Err(e)) => {
+                eprintln!("warning: the save is corrupt and has been reset: {e}");
+                Self::default()
+            }
+            None => Self::default(),
+        }
+    }
+
+    pub fn save(&self) {
+        write_save(&serde_json::to_string_pretty(self).unwrap());
+    }
+
+    pub fn is_cleared(&self, level_name: &str) -> bool {
+        self.cleared_levels.contains(level_name)
+    }
+
+    pub fn best_medal(&self, level_name: &str) -> Option<Medal> {
+        self.medals.get(level_name).copied()
+    }
+
+    // only kept (and saved) if it beats the medal already earned there
+    pub fn record_medal(&mut self, level_name: &str, medal: Medal) {
+        // no medal yet compares as worse than any medal
+        if self.best_medal(level_name) < Some(medal) {
+            self.medals.insert(level_name.to_string(), medal);
+            self.save();
+        }
+    }
+
+    // written along with the next saved change rather than every frame
+    pub fn add_playtime(&mut self, seconds: f64) {
+        self.playtime += seconds;
+    }
+
+    pub fn record_kill(&mut self) {
+        self.kills += 1;
+        self.save();
+    }
+
+    pub fn record_run(&mut self) {
+        self.runs += 1;
+        self.save();
+    }
+
+    pub fn record_detection(&mut self) {
+        self.detections += 1;
+        self.save();
+    }
+
+    pub fn record_clear(&mut self, level_name: &str) {
+        self.cleared_levels.insert(level_name.to_string());
+        self.save();
+    }
+
+    pub fn record_settings(&mut self, settings: Settings) {
+        self.settings = settings;
+        self.save();
+    }
+}
+
+pub fn save_data() -> MutexGuard<'static, SaveData> {
+    SAVE.lock().unwrap()
+}
@@ -0,0 +1,162 @@
+use std::sync::{Mutex, MutexGuard};
+
+use lazy_static::lazy_static;
+use serde::{Deserialize, Serialize};
+
+use crate::save::save_data;
+
+lazy_static! {
+    static ref SETTINGS: Mutex<Settings> = Mutex::new(Settings::default());
+}
+
+#[derive(Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub enum Difficulty {
+    Easy,
+    Normal,
+    Hard,
+}
+
+impl Difficulty {
+    pub fn next(self) -> Self {
+        match self {
+            Self::Easy => Self::Normal,
+            Self::Normal => Self::Hard,
+            Self::Hard => Self::Easy,
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::Easy => "Easy",
+            Self::Normal => "Normal",
+            Self::Hard => "Hard",
+        }
+    }
+
+    // how much longer guards stay suspicious after losing sight of something
+    pub fn memory_scale(self) -> f32 {
+        match self {
+            Self::Easy => 0.5,
+            Self::Normal => 1.0,
+            Self::Hard => 2.0,
+        }
+    }
+}
+
+// what stays put while the bow is being drawn
+#[derive(Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub enum AimLock {
+    // move and aim freely
+    Off,
+    // the player can't walk, so the shot origin stays fixed
+    Movement,
+    // the aim keeps the direction it had when the draw started, even while walking
+    Direction,
+}
+
+// kept in the save, where options missing from older saves take their defaults
+#[derive(Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Settings {
+    pub vignette_enabled: bool,
+    // how much the screen edges are darkened, from 0 to 1
+    pub vignette_strength: f32,
+    // getting detected ends the run instead of resetting the level
+    pub permadeath: bool,
+    // lets the bow fire before it is fully drawn, scattering arrows the earlier it is released
+    pub spread_shot: bool,
+    // the size of the render targets relative to the screen, lower is faster but blurrier
+    pub render_scale: f32,
+    pub difficulty: Difficulty,
+    // arrows in flight at once, past this the oldest ones are removed
+    pub max_projectiles: usize,
+    // refuse to fire at the cap instead of removing the oldest arrow
+    pub refuse_shots_at_cap: bool,
+    // draws a chevron in front of each guard pointing where they're looking
+    pub facing_markers: bool,
+    // fires away from the cursor instead of towards it, for lining up bank shots
+    pub inverted_aim: bool,
+    // levels are flipped left to right as they load, for practicing them mirrored
+    pub mirror_mode: bool,
+    // briefly slows the game down whenever an arrow kills a guard
+    pub kill_slow_mo: bool,
+    // spawns extra guards along the authored patrol paths, 1.0 keeps the authored count
+    pub enemy_density: f32,
+    pub aim_lock: AimLock,
+    // the player looks around after standing still for a while, like the guards do
+    pub player_look_around: bool,
+    // highlights the guard that found the player until the level resets
+    pub reveal_culprit: bool,
+    // arrows stop on dead guards and push them instead of flying over them
+    pub arrows_hit_bodies: bool,
+    // draws the patrol routes over the level select previews, which are made once at startup
+    pub preview_patrols: bool,
+    // tells the player the level is clear and points out the nearest exit
+    pub clear_cue: bool,
+    // scales every sound, from 0 (muted) to 1
+    pub master_volume: f32,
+    // the debug shadow overlay
+    pub shadows: bool,
+    // guards path diagonally instead of zig-zagging, read when a level loads
+    pub diagonal_paths: bool,
+    // seconds of carrying a body before the player tires and slows down, None never tires
+    pub max_carry_time: Option<f64>,
+}
+
+impl Default for Settings {
+    fn default() -> Self {
+        Self {
+            vignette_enabled: true,
+            vignette_strength: 0.4,
+            permadeath: false,
+            spread_shot: false,
+            render_scale: 1.0,
+            difficulty: Difficulty::Normal,
+            max_projectiles: 64,
+            refuse_shots_at_cap: false,
+            facing_markers: false,
+            inverted_aim: false,
+            mirror_mode: false,
+            kill_slow_mo: true,
+            enemy_density: 1.0,
+            aim_lock: AimLock::Off,
+            player_look_around: true,
+            reveal_culprit: true,
+            arrows_hit_bodies: false,
+            preview_patrols: true,
+            clear_cue: true,
+            master_volume: 1.0,
+            shadows: false,
+            diagonal_paths: false,
+            max_carry_time: None,
+        }
+    }
+}
+
+impl Settings {
+    // the settings are kept in the save, which falls back to the defaults when it's missing or
+    // corrupt
+    pub fn load() -> Self {
+        save_data().settings.clone()
+    }
+
+    pub fn save(&self) {
+        save_data().record_settings(self.clone());
+    }
+
+    pub fn vignette(&self) -> f32 {
+        if self.vignette_enabled {
+            self.vignette_strength
+        } else {
+            0.0
+        }
+    }
+
+    pub fn render_scale(&self) -> f32 {
+        self.render_scale.clamp(0.5, 2.0)
+    }
+}
+
+pub fn settings() -> MutexGuard<'static, Settings> {
+    SETTINGS.lock().unwrap()
+}
@@ -1,29 +1,274 @@
-use std::{collections::HashMap, sync::Mutex};
+use std::{
+    collections::HashMap,
+    sync::{
+        atomic::{AtomicU32, AtomicUsize, Ordering},
+        Mutex,
+    },
+};
 
 use lazy_static::lazy_static;
 use macroquad::{
-    audio::{load_sound_from_bytes, play_sound, stop_sound, PlaySoundParams, Sound},
+    audio::{
+        load_sound_from_bytes, play_sound, set_sound_volume, stop_sound, PlaySoundParams, Sound,
+    },
     prelude::*,
 };
 
 use crate::draw_progress;
 
 lazy_static! {
-    static ref SOUNDS: Mutex<HashMap<String, Sound>> = Mutex::new(HashMap::new());
-    static ref PLAYING: Mutex<HashMap<String, Vec<Sound>>> = Mutex::new(HashMap::new());
+    // loops of a sound are spread over its voices, so each can be stopped and turned down alone
+    static ref SOUNDS: Mutex<HashMap<String, Vec<Sound>>> = Mutex::new(HashMap::new());
+    // the looped sounds that are currently playing, or still playing their intro
+    static ref PLAYING: Mutex<Loops> = Mutex::new(Loops::default());
+    // sounds with an intro, and how long the full sound is before its loop takes over
+    static ref INTRO_DURATIONS: Mutex<HashMap<String, f64>> = Mutex::new(HashMap::new());
+    // stands in for any sound that failed to load or was never loaded
+    static ref SILENCE: Mutex<Option<Sound>> = Mutex::new(None);
+}
+
+// keeps playing through scene changes
+const MUSIC: &[&str] = &["bg_music"];
+
+// the guards' voice cues borrow other sounds until they get recordings of their own
+const CUES: &[(&str, &str)] = &[
+    ("guard_suspicious", "menu_tick"),
+    ("guard_intruder", "wrong"),
+    ("guard_nothing", "arrow_bounce"),
+];
+
+// how many copies of a sound that loops get loaded, one for each loop that can play at once
+const LOOP_VOICES: usize = 4;
+
+static NEXT_HANDLE: AtomicUsize = AtomicUsize::new(0);
+// the bits of an f32, so any thread can read it without taking a lock
+static MASTER_VOLUME: AtomicU32 = AtomicU32::new(0x3f80_0000);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct SoundHandle(usize);
+
+// a loop's handle, the voice it is using and the volume it asked for before the master volume
+type PlayingLoop = (SoundHandle, usize, f32);
+
+// a loop still playing its intro: its handle, the sound, when its loop should start and its volume
+type PendingLoop = (SoundHandle, String, f64, f32);
+
+// which voice each playing loop has, by sound name
+#[derive(Default)]
+struct Loops {
+    playing: HashMap<String, Vec<PlayingLoop>>,
+    // intros are only ever played on the first voice
+    intros: Vec<PendingLoop>,
+}
+
+impl Loops {
+    // the loop gets the first free voice, once every voice is busy further loops share the first
+    fn start(&mut self, name: &str, num_voices: usize, handle: SoundHandle, volume: f32) -> usize {
+        let in_use = self.playing.entry(name.to_string()).or_default();
+        let idx = (0..num_voices)
+            .find(|idx| !in_use.iter().any(|(_, v, _)| v == idx))
+            .unwrap_or_else(|| {
+                eprintln!("every voice of sound {name} is busy");
+                0
+            });
+        in_use.push((handle, idx, volume));
+        idx
+    }
+
+    fn start_intro(&mut self, name: &str, handle: SoundHandle, loop_start: f64, volume: f32) {
+        self.intros
+            .push((handle, name.to_string(), loop_start, volume));
+    }
+
+    // the intros that are over by `now`, their loops take over under the same handles
+    fn finish_intros(&mut self, now: f64) -> Vec<(SoundHandle, String, f32)> {
+        let (due, pending) = std::mem::take(&mut self.intros)
+            .into_iter()
+            .partition::<Vec<_>, _>(|(_, _, start, _)| *start <= now);
+        self.intros = pending;
+        due.into_iter()
+            .map(|(handle, name, _, volume)| (handle, name, volume))
+            .collect()
+    }
+
+    // the voice to turn to the new volume
+    fn set_volume(&mut self, handle: SoundHandle, volume: f32) -> Option<(String, usize)> {
+        if let Some((_, name, _, requested)) = self.intros.iter_mut().find(|(h, ..)| *h == handle) {
+            *requested = volume;
+            return Some((name.clone(), 0));
+        }
+        self.playing.iter_mut().find_map(|(name, in_use)| {
+            let (_, idx, requested) = in_use.iter_mut().find(|(h, ..)| *h == handle)?;
+            *requested = volume;
+            Some((name.clone(), *idx))
+        })
+    }
+
+    // the voice to stop, unless another loop is still sharing it
+    fn stop(&mut self, handle: SoundHandle) -> Option<(String, usize)> {
+        if let Some(pos) = self.intros.iter().position(|(h, ..)| *h == handle) {
+            let (_, name, _, _) = self.intros.remove(pos);
+            let shared = self.intros.iter().any(|(_, n, ..)| *n == name);
+            return (!shared).then_some((name, 0));
+        }
+        self.playing.iter_mut().find_map(|(name, in_use)| {
+            let pos = in_use.iter().position(|(h, ..)| *h == handle)?;
+            let (_, idx, _) = in_use.remove(pos);
+            (!in_use.iter().any(|(_, v, _)| *v == idx)).then(|| (name.clone(), idx))
+        })
+    }
+
+    fn stop_named(&mut self, name: &str) {
+        self.intros.retain(|(_, n, ..)| n != name);
+        self.playing.remove(name);
+    }
+}
+
+// a few samples of 16 bit mono silence
+fn silent_wav() -> Vec<u8> {
+    let sample_rate = 22050u32;
+    let data_len = 32u32;
+    let mut wav = Vec::new();
+    wav.extend_from_slice(b"RIFF");
+    wav.extend_from_slice(&(36 + data_len).to_le_bytes());
+    wav.extend_from_slice(b"WAVEfmt ");
+    wav.extend_from_slice(&16u32.to_le_bytes());
+    wav.extend_from_slice(&1u16.to_le_bytes());
+    wav.extend_from_slice(&1u16.to_le_bytes());
+    wav.extend_from_slice(&sample_rate.to_le_bytes());
+    wav.extend_from_slice(&(sample_rate * 2).to_le_bytes());
+    wav.extend_from_slice(&2u16.to_le_bytes());
+    wav.extend_from_slice(&16u16.to_le_bytes());
+    wav.extend_from_slice(b"data");
+    wav.extend_from_slice(&data_len.to_le_bytes());
+    wav.resize(wav.len() + data_len as usize, 0);
+    wav
+}
+
+fn silence() -> Sound {
+    SILENCE
+        .lock()
+        .unwrap()
+        .clone()
+        .expect("sounds are used before load_sounds")
+}
+
+// a broken sound is logged and replaced with silence rather than stopping the game from starting
+async fn load_or_silence(name: &str, sound_bytes: &[u8]) -> Sound {
+    match load_sound_from_bytes(sound_bytes).await {
+        Ok(sound) => sound,
+        Err(e) => {
+            eprintln!("could not load sound {name}: {e:?}");
+            silence()
+        }
+    }
+}
+
+async fn load_voices(name: &str, sound_bytes: &[u8], count: usize) -> Vec<Sound> {
+    let mut voices = Vec::with_capacity(count);
+    for _ in 0..count {
+        voices.push(load_or_silence(name, sound_bytes).await);
+    }
+    voices
 }
 
 async fn load_sound_with_feedback(name: &str, sound_bytes: &[u8]) {
-    let sound = load_sound_from_bytes(sound_bytes).await.unwrap();
-    SOUNDS.lock().unwrap().insert(name.to_string(), sound);
+    let voices = load_voices(name, sound_bytes, 1).await;
+    SOUNDS.lock().unwrap().insert(name.to_string(), voices);
     draw_progress("Loading Sounds", SOUNDS.lock().unwrap().len() as f32 / 11.0).await;
 }
 
+async fn load_looping_sound(name: &str, sound_bytes: &[u8]) {
+    let voices = load_voices(name, sound_bytes, LOOP_VOICES).await;
+    SOUNDS.lock().unwrap().insert(name.to_string(), voices);
+    draw_progress("Loading Sounds", SOUNDS.lock().unwrap().len() as f32 / 11.0).await;
+}
+
+// the sample data of a wav file: (offset, length, bytes per second, bytes per sample frame)
+fn wav_data(bytes: &[u8]) -> Option<(usize, usize, usize, usize)> {
+    if bytes.len() < 12 || &bytes[0..4] != b"RIFF" || &bytes[8..12] != b"WAVE" {
+        return None;
+    }
+    let read_u32 = |i: usize| u32::from_le_bytes(bytes[i..i + 4].try_into().unwrap()) as usize;
+    let read_u16 = |i: usize| u16::from_le_bytes(bytes[i..i + 2].try_into().unwrap()) as usize;
+
+    let mut format = None;
+    let mut chunk = 12;
+    while chunk + 8 <= bytes.len() {
+        let len = read_u32(chunk + 4);
+        let body = chunk + 8;
+        match &bytes[chunk..chunk + 4] {
+            b"fmt " if body + 16 <= bytes.len() => {
+                format = Some((read_u32(body + 8), read_u16(body + 12)));
+            }
+            b"data" => {
+                let (byte_rate, block_align) = format?;
+                return Some((body, len.min(bytes.len() - body), byte_rate, block_align));
+            }
+            _ => {}
+        }
+        chunk = body + len + len % 2;
+    }
+    None
+}
+
+// a copy of a wav file with everything before `start` seconds cut off
+fn trim_wav_start(bytes: &[u8], start: f64) -> Option<Vec<u8>> {
+    let (offset, len, byte_rate, block_align) = wav_data(bytes)?;
+    let cut = ((start * byte_rate as f64) as usize / block_align * block_align).min(len);
+
+    let mut trimmed = bytes[..offset].to_vec();
+    trimmed.extend_from_slice(&bytes[offset + cut..offset + len]);
+    let data_len = (len - cut) as u32;
+    trimmed[offset - 4..offset].copy_from_slice(&data_len.to_le_bytes());
+    let riff_len = (trimmed.len() - 8) as u32;
+    trimmed[4..8].copy_from_slice(&riff_len.to_le_bytes());
+    Some(trimmed)
+}
+
+fn loop_name(name: &str) -> String {
+    format!("{name}_loop")
+}
+
+/// Loads a sound that, when looped, plays through once and then loops from `loop_start`
+/// seconds in, so an intro doesn't replay every time around.
+async fn load_sound_with_loop_point(name: &str, sound_bytes: &[u8], loop_start: f64) {
+    load_looping_sound(name, sound_bytes).await;
+    if loop_start <= 0.0 {
+        return;
+    }
+
+    // anything we can't cut up just loops from the start
+    let (len, byte_rate, body) = match (
+        wav_data(sound_bytes),
+        trim_wav_start(sound_bytes, loop_start),
+    ) {
+        (Some((_, len, byte_rate, _)), Some(body)) => (len, byte_rate, body),
+        _ => {
+            eprintln!("could not find the loop point of sound {name}");
+            return;
+        }
+    };
+    let voices = load_voices(&loop_name(name), &body, LOOP_VOICES).await;
+    SOUNDS.lock().unwrap().insert(loop_name(name), voices);
+    INTRO_DURATIONS
+        .lock()
+        .unwrap()
+        .insert(name.to_string(), len as f64 / byte_rate as f64);
+}
+
 pub async fn load_sounds() {
     draw_progress("Loading Sounds", 0.0).await;
-    load_sound_with_feedback("alarm", include_bytes!("../assets/sounds/alarm.wav")).await;
+    *SILENCE.lock().unwrap() = Some(load_sound_from_bytes(&silent_wav()).await.unwrap());
+    load_looping_sound("alarm", include_bytes!("../assets/sounds/alarm.wav")).await;
     load_sound_with_feedback("arrow_shoot", include_bytes!("../assets/sounds/arrow.wav")).await;
-    load_sound_with_feedback("bg_music", include_bytes!("../assets/sounds/bg_music.wav")).await;
+    // move the loop start to the end of the intro if the track gets one
+    load_sound_with_loop_point(
+        "bg_music",
+        include_bytes!("../assets/sounds/bg_music.wav"),
+        0.0,
+    )
+    .await;
     load_sound_with_feedback("win", include_bytes!("../assets/sounds/Retro Event 49.wav")).await;
     load_sound_with_feedback(
         "menu_tick",
@@ -62,15 +307,220 @@ pub async fn load_sounds() {
     .await;
 }
 
+pub fn master_volume() -> f32 {
+    f32::from_bits(MASTER_VOLUME.load(Ordering::Relaxed))
+}
+
+/// Scales every sound from now on, and the loops that are already playing.
+pub fn set_master_volume(volume: f32) {
+    let volume = volume.clamp(0.0, 1.0);
+    MASTER_VOLUME.store(volume.to_bits(), Ordering::Relaxed);
+    let loops = PLAYING.lock().unwrap();
+    for (name, in_use) in loops.playing.iter() {
+        for &(_, idx, requested) in in_use.iter() {
+            set_sound_volume(&voice(name, idx), mixed_volume(requested));
+        }
+    }
+    for (_, name, _, requested) in loops.intros.iter() {
+        set_sound_volume(&sound(name), mixed_volume(*requested));
+    }
+}
+
+fn mixed_volume(volume: f32) -> f32 {
+    volume * master_volume() / 3.0
+}
+
 pub fn sound(name: &str) -> Sound {
-    SOUNDS.lock().unwrap().get(name).unwrap().clone()
+    voice(name, 0)
+}
+
+fn voice(name: &str, idx: usize) -> Sound {
+    let name = CUES
+        .iter()
+        .find(|(cue, _)| *cue == name)
+        .map_or(name, |(_, sound)| sound);
+    match SOUNDS.lock().unwrap().get(name) {
+        Some(voices) => voices[idx].clone(),
+        None => {
+            eprintln!("no sound named {name}");
+            silence()
+        }
+    }
+}
+
+/// Plays a one-shot that gets quieter the further `source` is from `listener`.
+pub fn play_at(name: &str, volume: f32, source: Vec2, listener: Vec2) {
+    let max_distance = 400.0;
+    let falloff = 1.0 - ((source - listener).length() / max_distance).min(1.0);
+    if falloff > 0.0 {
+        play(name, volume * falloff, false);
+    }
+}
+
+pub fn play(name: &str, volume: f32, looped: bool) -> SoundHandle {
+    let handle = SoundHandle(NEXT_HANDLE.fetch_add(1, Ordering::Relaxed));
+    // nothing is loaded yet, as in the tests, which never call load_sounds
+    if SILENCE.lock().unwrap().is_none() {
+        return handle;
+    }
+    let requested = volume;
+    let volume = mixed_volume(volume);
+    if !looped {
+        play_sound(&sound(name), PlaySoundParams { volume, looped });
+        return handle;
+    }
+    // the intro plays once, then `update_loops` hands over to the loop
+    if let Some(&duration) = INTRO_DURATIONS.lock().unwrap().get(name) {
+        play_sound(
+            &sound(name),
+            PlaySoundParams {
+                volume,
+                looped: false,
+            },
+        );
+        PLAYING
+            .lock()
+            .unwrap()
+            .start_intro(name, handle, get_time() + duration, requested);
+        return handle;
+    }
+    start_loop(name, handle, requested);
+    handle
 }
 
-pub fn play(name: &str, volume: f32, looped: bool) {
-    let volume = volume / 3.0;
-    play_sound(&sound(name), PlaySoundParams { volume, looped });
+fn start_loop(name: &str, handle: SoundHandle, volume: f32) {
+    let num_voices = SOUNDS.lock().unwrap().get(name).map_or(1, |v| v.len());
+    let idx = PLAYING
+        .lock()
+        .unwrap()
+        .start(name, num_voices, handle, volume);
+    play_sound(
+        &voice(name, idx),
+        PlaySoundParams {
+            volume: mixed_volume(volume),
+            looped: true,
+        },
+    );
+}
+
+pub fn set_handle_volume(handle: SoundHandle, volume: f32) {
+    let changed = PLAYING.lock().unwrap().set_volume(handle, volume);
+    if let Some((name, idx)) = changed {
+        set_sound_volume(&voice(&name, idx), mixed_volume(volume));
+    }
+}
+
+pub fn stop_handle(handle: SoundHandle) {
+    let stopped = PLAYING.lock().unwrap().stop(handle);
+    if let Some((name, idx)) = stopped {
+        stop_sound(&voice(&name, idx));
+    }
+}
+
+/// Starts the loops of any intros that have finished, call this every frame.
+pub fn update_loops() {
+    let due = PLAYING.lock().unwrap().finish_intros(get_time());
+    for (handle, name, volume) in due {
+        start_loop(&loop_name(&name), handle, volume);
+    }
 }
 
 pub fn stop(name: &str) {
-    stop_sound(&sound(name));
+    if INTRO_DURATIONS.lock().unwrap().contains_key(name) {
+        stop(&loop_name(name));
+    }
+    PLAYING.lock().unwrap().stop_named(name);
+    if let Some(voices) = SOUNDS.lock().unwrap().get(name) {
+        for voice in voices.iter() {
+            stop_sound(voice);
+        }
+    }
+}
+
+/// Stops every sound but the music, for when the scene changes under them.
+pub fn stop_all() {
+    let names = SOUNDS
+        .lock()
+        .unwrap()
+        .keys()
+        .filter(|name| !MUSIC.iter().any(|m| *name == m || **name == loop_name(m)))
+        .cloned()
+        .collect::<Vec<_>>();
+    for name in names {
+        stop(&name);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn loops_of_one_sound_get_their_own_voices() {
+        let mut loops = Loops::default();
+        let (first, second) = (SoundHandle(0), SoundHandle(1));
+        let first_voice = loops.start("alarm", LOOP_VOICES, first, 1.0);
+        let second_voice = loops.start("alarm", LOOP_VOICES, second, 1.0);
+        assert_ne!(first_voice, second_voice);
+
+        // turning one down leaves the other as it was
+        assert_eq!(
+            loops.set_volume(second, 0.25),
+            Some(("alarm".to_string(), second_voice))
+        );
+        let volumes = loops.playing["alarm"]
+            .iter()
+            .map(|&(h, _, v)| (h, v))
+            .collect::<Vec<_>>();
+        assert_eq!(volumes, vec![(first, 1.0), (second, 0.25)]);
+
+        // stopping one stops only its voice, the other keeps playing and can still be turned down
+        assert_eq!(loops.stop(first), Some(("alarm".to_string(), first_voice)));
+        assert_eq!(loops.stop(first), None);
+        assert_eq!(
+            loops.set_volume(second, 0.5),
+            Some(("alarm".to_string(), second_voice))
+        );
+        assert_eq!(
+            loops.stop(second),
+            Some(("alarm".to_string(), second_voice))
+        );
+    }
+
+    #[test]
+    fn loops_share_a_voice_once_every_voice_is_busy() {
+        let mut loops = Loops::default();
+        let shared = loops.start("footstep", 1, SoundHandle(0), 1.0);
+        assert_eq!(loops.start("footstep", 1, SoundHandle(1), 1.0), shared);
+
+        // the voice keeps playing until the last loop on it stops
+        assert_eq!(loops.stop(SoundHandle(0)), None);
+        assert_eq!(
+            loops.stop(SoundHandle(1)),
+            Some(("footstep".to_string(), shared))
+        );
+    }
+    #[test]
+    fn an_intro_hands_its_handle_over_to_its_loop() {
+        let mut loops = Loops::default();
+        let (music, other) = (SoundHandle(0), SoundHandle(1));
+        loops.start_intro("bg_music", music, 10.0, 1.0);
+        loops.start_intro("bg_music", other, 10.0, 1.0);
+
+        // the intro can be turned down and stopped by its handle before the loop starts
+        assert_eq!(
+            loops.set_volume(music, 0.5),
+            Some(("bg_music".to_string(), 0))
+        );
+        assert_eq!(loops.stop(other), None);
+        assert!(loops.finish_intros(5.0).is_empty());
+
+        // the loop starts under the same handle, at the volume it was last given
+        assert_eq!(
+            loops.finish_intros(10.0),
+            vec![(music, "bg_music".to_string(), 0.5)]
+        );
+        let idx = loops.start("bg_music_loop", LOOP_VOICES, music, 0.5);
+        assert_eq!(loops.stop(music), Some(("bg_music_loop".to_string(), idx)));
+    }
 }
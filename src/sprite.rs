@@ -8,6 +8,17 @@ use macroquad::prelude::*;
 
 use crate::{bindata::load_fast_texture, texturepacker::TexturePackerData, Time};
 
+// the tests have no macroquad context to ask for the time, so their animations stand still
+#[cfg(not(test))]
+fn now() -> Time {
+    get_time()
+}
+
+#[cfg(test)]
+fn now() -> Time {
+    0.0
+}
+
 pub struct SpriteSheet {
     pub texture: Texture2D,
     pub data: Option<TexturePackerData>,
@@ -48,7 +59,7 @@ impl SpriteSheet {
                 frames: Frames::Multiple {
                     frames,
                     curr: Cell::new(0),
-                    since: Cell::new(get_time()),
+                    since: Cell::new(now()),
                     frame_time: Cell::new(frame_time),
                 },
                 texture: self.texture.clone(),
@@ -89,7 +100,7 @@ impl SpriteSheet {
             frames: Frames::Multiple {
                 frames: frames.to_vec(),
                 curr: Cell::new(0),
-                since: Cell::new(get_time()),
+                since: Cell::new(now()),
                 frame_time: Cell::new(frame_time),
             },
             flip_x: false,
@@ -120,11 +131,15 @@ impl Sprite {
     }
 
     pub fn draw(&self, pos: Vec2) {
+        self.draw_colored(pos, WHITE);
+    }
+
+    pub fn draw_colored(&self, pos: Vec2, color: Color) {
         draw_texture_ex(
             &self.texture,
             pos.x,
             pos.y,
-            WHITE,
+            color,
             DrawTextureParams {
                 source: Some(self.frames.get()),
                 flip_x: self.flip_x,
@@ -148,6 +163,10 @@ impl Sprite {
         }
     }
 
+    pub fn toggle_flip_x(&mut self) {
+        self.flip_x = !self.flip_x;
+    }
+
     pub fn reset(&self) {
         self.frames.reset();
     }
@@ -159,6 +178,14 @@ impl Sprite {
     pub fn about_to_loop(&self) -> bool {
         self.frames.about_to_loop()
     }
+
+    pub fn set_frame_time(&self, frame_time: Time) {
+        self.frames.set_frame_time(frame_time);
+    }
+
+    pub fn curr_frame(&self) -> usize {
+        self.frames.curr_frame()
+    }
 }
 
 impl Clone for Sprite {
@@ -195,9 +222,9 @@ impl Frames {
                 since,
                 frame_time,
             } => {
-                if get_time() - since.get() > frame_time.get() {
+                if now() - since.get() > frame_time.get() {
                     curr.set((curr.get() + 1) % frames.len());
-                    since.set(get_time());
+                    since.set(now());
                 }
                 frames[curr.get()]
             }
@@ -214,7 +241,7 @@ impl Frames {
                 frame_time,
             } => {
                 if curr.get() == frames.len() - 1 {
-                    get_time() - since.get() + 2.0 * get_frame_time() as Time >= frame_time.get()
+                    now() - since.get() + 2.0 * get_frame_time() as Time >= frame_time.get()
                 } else {
                     false
                 }
@@ -227,10 +254,24 @@ impl Frames {
             Frames::Single(_) => {}
             Frames::Multiple { curr, since, .. } => {
                 curr.set(0);
-                since.set(get_time());
+                since.set(now());
             }
         }
     }
+
+    pub fn curr_frame(&self) -> usize {
+        match self {
+            Frames::Single(_) => 0,
+            Frames::Multiple { curr, .. } => curr.get(),
+        }
+    }
+
+    pub fn set_frame_time(&self, new_frame_time: Time) {
+        match self {
+            Frames::Single(_) => {}
+            Frames::Multiple { frame_time, .. } => frame_time.set(new_frame_time),
+        }
+    }
 }
 
 pub struct AnimationAtlas {
@@ -300,6 +341,8 @@ pub fn anim_rects(rect: Rect, stride_x: f32, count: i32) -> Vec<Rect> {
     rects
 }
 
+// cloning copies every sprite's frame counters along with it, Cell and RefCell clone their
+// values rather than sharing them, so each guard's clone animates on its own
 #[derive(Debug, Clone)]
 pub struct SpriteMap {
     map: HashMap<String, Sprite>,
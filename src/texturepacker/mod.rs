@@ -1,6 +1,10 @@
-
 use macroquad::prelude::*;
-use std::{collections::HashMap, fs::read_to_string, path::Path};
+use std::{collections::HashMap, path::Path};
+
+#[cfg(not(target_arch = "wasm32"))]
+use std::fs::read_to_string;
+
+use crate::bindata::embedded_atlas_json;
 
 use serde::{Deserialize, Serialize};
 use serde_json as json;
@@ -54,6 +58,27 @@ impl Into<Rect> for TexturePackerRect {
 
 impl TexturePackerData {
     pub fn new(path: impl AsRef<Path>) -> Self {
-        json::from_str(&read_to_string(path).unwrap()).unwrap()
+        match embedded_atlas_json(path.as_ref()) {
+            Some(json) => Self::from_str(json),
+            None => Self::from_file(path),
+        }
+    }
+
+    #[cfg(not(target_arch = "wasm32"))]
+    fn from_file(path: impl AsRef<Path>) -> Self {
+        Self::from_str(&read_to_string(path).unwrap())
+    }
+
+    // there is no filesystem on web, so the atlas has to be embedded in bindata
+    #[cfg(target_arch = "wasm32")]
+    fn from_file(path: impl AsRef<Path>) -> Self {
+        panic!(
+            "atlas {} is not embedded, add it to ATLAS_JSON in bindata.rs",
+            path.as_ref().display()
+        )
+    }
+
+    pub fn from_str(s: &str) -> Self {
+        json::from_str(s).unwrap()
     }
-}
\ No newline at end of file
+}
\ No newline at end of file
@@ -2,27 +2,199 @@ use macroquad::prelude::*;
 
 use crate::{
     get_width_height,
-    sounds::play,
+    replay::{is_key_pressed, is_mouse_button_pressed, mouse_position, mouse_wheel},
+    save::save_data,
+    settings::{settings, AimLock},
+    sounds::{play, set_master_volume},
     sprite::{anim_rects, SpriteMap, SpriteSheet},
     text_helpers::{draw_text_aligned, TextAlign},
+    Time,
 };
 
 #[derive(Clone, Copy)]
 enum ButtonAction {
     StartGame(i32),
     GoToControls,
+    GoToOptions,
     GoToLevelSelect,
+    GoToStats,
     GoHome,
     DisableUi,
+    ChangeSetting(Setting),
     Quit,
 }
 
+// a row on the options screen, which toggles or cycles one setting
+#[derive(Clone, Copy)]
+enum Setting {
+    Vignette,
+    VignetteStrength,
+    Permadeath,
+    SpreadShot,
+    RenderScale,
+    Difficulty,
+    FacingMarkers,
+    InvertedAim,
+    MirrorMode,
+    KillSlowMo,
+    EnemyDensity,
+    AimLock,
+    PlayerLookAround,
+    RevealCulprit,
+    ArrowsHitBodies,
+    MaxProjectiles,
+    RefuseShotsAtCap,
+    PreviewPatrols,
+    DiagonalPaths,
+    ClearCue,
+    MasterVolume,
+}
+
+// in the order they're listed on the options screen
+const OPTIONS: [Setting; 21] = [
+    Setting::MasterVolume,
+    Setting::Difficulty,
+    Setting::Permadeath,
+    Setting::SpreadShot,
+    Setting::AimLock,
+    Setting::InvertedAim,
+    Setting::FacingMarkers,
+    Setting::RevealCulprit,
+    Setting::ClearCue,
+    Setting::KillSlowMo,
+    Setting::ArrowsHitBodies,
+    Setting::MaxProjectiles,
+    Setting::RefuseShotsAtCap,
+    Setting::EnemyDensity,
+    Setting::DiagonalPaths,
+    Setting::MirrorMode,
+    Setting::PlayerLookAround,
+    Setting::PreviewPatrols,
+    Setting::Vignette,
+    Setting::VignetteStrength,
+    Setting::RenderScale,
+];
+
+// how many options rows fit on the screen at once, the rest are scrolled to
+const VISIBLE_OPTIONS: usize = 8;
+
+const MY_GREEN: Color = Color::new(0.35, 0.75, 0.4, 1.0);
+
+// a tick whose short stroke starts at `top_left`
+fn draw_checkmark(top_left: Vec2, color: Color) {
+    let a = top_left + vec2(0.0, 10.0);
+    let b = top_left + vec2(7.0, 17.0);
+    let c = top_left + vec2(20.0, 0.0);
+    draw_line(a.x, a.y, b.x, b.y, 4.0, color);
+    draw_line(b.x, b.y, c.x, c.y, 4.0, color);
+}
+
+fn on_off(name: &str, on: bool) -> String {
+    format!("{name}: {}", if on { "On" } else { "Off" })
+}
+
+// the next value after `curr` in `values`, wrapping around, or the first if `curr` isn't one
+fn cycle<T: Copy + PartialEq>(values: &[T], curr: T) -> T {
+    let next = values
+        .iter()
+        .position(|&v| v == curr)
+        .map(|i| (i + 1) % values.len())
+        .unwrap_or(0);
+    values[next]
+}
+
+impl Setting {
+    fn label(self) -> String {
+        let settings = settings();
+        match self {
+            Self::Vignette => on_off("Vignette", settings.vignette_enabled),
+            Self::VignetteStrength => {
+                format!("Darkening: {:.0}%", settings.vignette_strength * 100.0)
+            }
+            Self::Permadeath => on_off("Permadeath", settings.permadeath),
+            Self::SpreadShot => on_off("Spread Shot", settings.spread_shot),
+            Self::RenderScale => format!("Scale: {:.1}x", settings.render_scale()),
+            Self::Difficulty => settings.difficulty.name().to_owned(),
+            Self::FacingMarkers => on_off("Facing", settings.facing_markers),
+            Self::InvertedAim => on_off("Inverted Aim", settings.inverted_aim),
+            Self::MirrorMode => on_off("Mirror Mode", settings.mirror_mode),
+            Self::KillSlowMo => on_off("Slow-Mo", settings.kill_slow_mo),
+            Self::EnemyDensity => format!("Guards: {:.1}x", settings.enemy_density),
+            Self::AimLock => format!(
+                "Aim Lock: {}",
+                match settings.aim_lock {
+                    AimLock::Off => "Off",
+                    AimLock::Movement => "Move",
+                    AimLock::Direction => "Aim",
+                }
+            ),
+            Self::PlayerLookAround => on_off("Idle Anim", settings.player_look_around),
+            Self::RevealCulprit => on_off("Culprit", settings.reveal_culprit),
+            Self::ArrowsHitBodies => on_off("Body Hits", settings.arrows_hit_bodies),
+            Self::MaxProjectiles => format!("Arrow Cap: {}", settings.max_projectiles),
+            Self::RefuseShotsAtCap => on_off("Cap Stops", settings.refuse_shots_at_cap),
+            Self::PreviewPatrols => on_off("Map Routes", settings.preview_patrols),
+            // the guards' paths are worked out when a level loads, so this applies from the next
+            Self::DiagonalPaths => on_off("Diagonals", settings.diagonal_paths),
+            Self::ClearCue => on_off("Exit Cue", settings.clear_cue),
+            Self::MasterVolume => format!("Volume: {:.0}%", settings.master_volume * 100.0),
+        }
+    }
+
+    fn change(self) {
+        let mut settings = settings();
+        match self {
+            Self::Vignette => settings.vignette_enabled = !settings.vignette_enabled,
+            Self::VignetteStrength => {
+                settings.vignette_strength =
+                    cycle(&[0.2, 0.4, 0.6, 0.8, 1.0], settings.vignette_strength)
+            }
+            Self::Permadeath => settings.permadeath = !settings.permadeath,
+            Self::SpreadShot => settings.spread_shot = !settings.spread_shot,
+            Self::RenderScale => {
+                settings.render_scale = cycle(&[0.5, 1.0, 1.5, 2.0], settings.render_scale)
+            }
+            Self::Difficulty => settings.difficulty = settings.difficulty.next(),
+            Self::FacingMarkers => settings.facing_markers = !settings.facing_markers,
+            Self::InvertedAim => settings.inverted_aim = !settings.inverted_aim,
+            Self::MirrorMode => settings.mirror_mode = !settings.mirror_mode,
+            Self::KillSlowMo => settings.kill_slow_mo = !settings.kill_slow_mo,
+            Self::EnemyDensity => {
+                settings.enemy_density = cycle(&[1.0, 1.5, 2.0, 3.0], settings.enemy_density)
+            }
+            Self::AimLock => {
+                settings.aim_lock = cycle(
+                    &[AimLock::Off, AimLock::Movement, AimLock::Direction],
+                    settings.aim_lock,
+                )
+            }
+            Self::PlayerLookAround => settings.player_look_around = !settings.player_look_around,
+            Self::RevealCulprit => settings.reveal_culprit = !settings.reveal_culprit,
+            Self::ArrowsHitBodies => settings.arrows_hit_bodies = !settings.arrows_hit_bodies,
+            Self::MaxProjectiles => {
+                settings.max_projectiles = cycle(&[16, 32, 64, 128], settings.max_projectiles)
+            }
+            Self::RefuseShotsAtCap => settings.refuse_shots_at_cap = !settings.refuse_shots_at_cap,
+            Self::PreviewPatrols => settings.preview_patrols = !settings.preview_patrols,
+            Self::DiagonalPaths => settings.diagonal_paths = !settings.diagonal_paths,
+            Self::ClearCue => settings.clear_cue = !settings.clear_cue,
+            Self::MasterVolume => {
+                settings.master_volume = cycle(&[0.0, 0.25, 0.5, 0.75, 1.0], settings.master_volume)
+            }
+        }
+    }
+}
+
 enum UiState {
     Disabled,
     MainMenu,
     Controls,
+    Options,
     LevelSelect,
+    Stats,
     PopUp(String),
+    // the level the run ended on and how long it lasted
+    GameOver(String, Time),
 }
 
 pub enum UiAction {
@@ -46,8 +218,13 @@ pub struct Ui {
     selected_button: usize,
     ignore_mousepos: Option<Vec2>,
     state: UiState,
-    levels: Vec<(String, RenderTarget)>,
+    // each level's preview without and with its patrol routes, so Map Routes shows right away
+    levels: Vec<(String, RenderTarget, RenderTarget)>,
     pub curr_level: usize,
+    // shown while playing, without pausing the game
+    hint: Option<String>,
+    // the first of the options rows currently on screen
+    options_scroll: usize,
 }
 
 impl Ui {
@@ -58,7 +235,7 @@ impl Ui {
         self.camera =
             Camera2D::from_display_rect(Rect::new(0.0, self.height, self.width, -self.height));
     }
-    pub async fn new(levels: Vec<(String, RenderTarget)>) -> Self {
+    pub async fn new(levels: Vec<(String, RenderTarget, RenderTarget)>) -> Self {
         // TODO: resize camera
         let (width, height) = get_width_height(512.0);
         let camera = Camera2D::from_display_rect(Rect::new(0.0, height, width, -height));
@@ -234,21 +411,40 @@ impl Ui {
             levels,
             curr_level: 0,
             ignore_mousepos: None,
+            hint: None,
+            options_scroll: 0,
         }
     }
 
     fn dispatch_action(&mut self, action: ButtonAction) -> UiAction {
-        self.selected_button = 0;
+        // toggles stay on the same screen, so keep the selection where it is
+        if !matches!(action, ButtonAction::ChangeSetting(_)) {
+            self.selected_button = 0;
+        }
         match action {
             ButtonAction::StartGame(idx) => {
                 self.state = UiState::Disabled;
                 return UiAction::SwitchLevel(self.levels[idx as usize].0.clone());
             }
             ButtonAction::GoToControls => self.state = UiState::Controls,
+            ButtonAction::GoToOptions => {
+                self.options_scroll = 0;
+                self.state = UiState::Options;
+            }
+            ButtonAction::GoToStats => self.state = UiState::Stats,
             ButtonAction::GoToLevelSelect => self.state = UiState::LevelSelect,
             ButtonAction::DisableUi => self.state = UiState::Disabled,
             ButtonAction::GoHome => self.state = UiState::MainMenu,
-            ButtonAction::Quit => return UiAction::Quit,
+            ButtonAction::ChangeSetting(setting) => {
+                setting.change();
+                set_master_volume(settings().master_volume);
+                settings().save();
+            }
+            ButtonAction::Quit => {
+                // quitting from a level lands on the main menu
+                self.state = UiState::MainMenu;
+                return UiAction::Quit;
+            }
         }
         UiAction::None
     }
@@ -265,11 +461,30 @@ impl Ui {
         }
     }
 
+    pub fn close(&mut self) {
+        self.state = UiState::Disabled;
+    }
+
+    // opens the menu as if escape was pressed
+    pub fn pause(&mut self) {
+        // a good moment to save the playtime so far
+        save_data().save();
+        self.selected_button = 0;
+        self.state = UiState::MainMenu;
+    }
+
+    pub fn game_over(&mut self, level: &str, time: Time) {
+        self.selected_button = 0;
+        self.state = UiState::GameOver(level.to_owned(), time);
+    }
+
     pub fn tick(&mut self, main_menu: bool) -> UiAction {
         if matches!(self.state, UiState::Disabled) && is_key_pressed(KeyCode::Escape) {
-            self.selected_button = 0;
-            self.state = UiState::MainMenu;
-        } else if !main_menu && !matches!(self.state, UiState::PopUp(_)) && is_key_pressed(KeyCode::Escape) {
+            self.pause();
+        } else if !main_menu
+            && !matches!(self.state, UiState::PopUp(_) | UiState::GameOver(..))
+            && is_key_pressed(KeyCode::Escape)
+        {
             self.state = UiState::Disabled;
         }
         if !self.is_enabled() {
@@ -295,6 +510,11 @@ impl Ui {
             }
         }
 
+        if matches!(self.state, UiState::Options) && (self.scroll_options() || self.slide_volume())
+        {
+            return UiAction::None;
+        }
+
         if is_key_pressed(KeyCode::Up) {
             play("menu_tick", 1.0, false);
             self.selected_button = (self.selected_button as isize - 1 + self.buttons.len() as isize)
@@ -316,6 +536,71 @@ impl Ui {
         UiAction::None
     }
 
+    // scrolls the options rows with the mouse wheel, or when the selection moves past the top or
+    // bottom row on screen, and returns whether that used up the key press
+    fn scroll_options(&mut self) -> bool {
+        let max_scroll = OPTIONS.len() - VISIBLE_OPTIONS;
+        let wheel = mouse_wheel().1;
+        if wheel < 0.0 {
+            self.options_scroll = (self.options_scroll + 1).min(max_scroll);
+        } else if wheel > 0.0 {
+            self.options_scroll = self.options_scroll.saturating_sub(1);
+        }
+
+        // the buttons are the rows on screen followed by Back
+        let back = VISIBLE_OPTIONS;
+        if is_key_pressed(KeyCode::Down) {
+            if self.selected_button == VISIBLE_OPTIONS - 1 && self.options_scroll < max_scroll {
+                play("menu_tick", 1.0, false);
+                self.options_scroll += 1;
+                return true;
+            }
+            // wrapping from Back to the top of the list
+            if self.selected_button == back {
+                self.options_scroll = 0;
+            }
+        }
+        if is_key_pressed(KeyCode::Up) {
+            if self.selected_button == 0 && self.options_scroll > 0 {
+                play("menu_tick", 1.0, false);
+                self.options_scroll -= 1;
+                return true;
+            }
+            // wrapping from the top of the list to Back, just below the bottom of the list
+            if self.selected_button == 0 {
+                self.options_scroll = max_scroll;
+            }
+        }
+        false
+    }
+
+    // left and right on the volume row act as a slider, so it can be turned down in either
+    // direction without wrapping through full volume
+    fn slide_volume(&mut self) -> bool {
+        if !matches!(
+            self.buttons[self.selected_button].action,
+            ButtonAction::ChangeSetting(Setting::MasterVolume)
+        ) {
+            return false;
+        }
+        let step = if is_key_pressed(KeyCode::Left) {
+            -0.25
+        } else if is_key_pressed(KeyCode::Right) {
+            0.25
+        } else {
+            return false;
+        };
+        let volume = {
+            let mut settings = settings();
+            settings.master_volume = (settings.master_volume + step).clamp(0.0, 1.0);
+            settings.master_volume
+        };
+        set_master_volume(volume);
+        settings().save();
+        play("menu_tick", 1.0, false);
+        true
+    }
+
     fn draw_container(&mut self, rect: Rect, name: &str) {
         assert!(rect.w % 32.0 == 0.0);
         assert!(rect.h % 32.0 == 0.0);
@@ -369,8 +654,60 @@ impl Ui {
         self.state = UiState::PopUp(text.to_string());
     }
 
+    pub fn show_hint(&mut self, text: &str) {
+        self.hint = Some(text.to_string());
+    }
+
+    pub fn clear_hint(&mut self) {
+        self.hint = None;
+    }
+
+    // draws `text` in a container along the bottom of the screen and returns the container
+    fn draw_text_box(&mut self, text: &str) -> Rect {
+        let container_width = 416.0;
+        let text_rect = draw_text_aligned(
+            text,
+            TextAlign::Left,
+            vec2(0.0, 0.0),
+            Some(container_width),
+            true,
+            TextParams {
+                font_size: 24,
+                font: self.font.as_ref(),
+                ..Default::default()
+            },
+        );
+
+        let container_height = ((text_rect.h / 32.0) as i32) as f32 * 32.0 + 32.0 * 2.0;
+        let topleft = vec2(
+            (self.width - container_width) / 2.0,
+            self.height - container_height - 30.0,
+        );
+        let container = Rect::new(topleft.x, topleft.y, container_width, container_height);
+
+        self.draw_container(container, "container1");
+
+        draw_text_aligned(
+            text,
+            TextAlign::Left,
+            topleft + vec2(0.0, 20.0),
+            Some(container_width),
+            false,
+            TextParams {
+                font_size: 32,
+                font_scale: 0.75,
+                font: self.font.as_ref(),
+                ..Default::default()
+            },
+        );
+        container
+    }
+
     pub fn draw(&mut self, main_menu: bool) {
         if let UiState::Disabled = self.state {
+            if let Some(hint) = self.hint.clone() {
+                self.draw_text_box(&hint);
+            }
             return;
         }
         self.buttons.clear();
@@ -386,7 +723,12 @@ impl Ui {
         );
 
         match self.state {
-            UiState::LevelSelect | UiState::Controls | UiState::MainMenu => {
+            UiState::LevelSelect
+            | UiState::Controls
+            | UiState::Options
+            | UiState::Stats
+            | UiState::MainMenu
+            | UiState::GameOver(..) => {
                 self.draw_container(
                     Rect::new(topleft.x, topleft.y, container_width, container_height),
                     "container1",
@@ -398,47 +740,9 @@ impl Ui {
         match &self.state {
             UiState::PopUp(text) => {
                 let text = text.clone();
-                let text_rect = draw_text_aligned(
-                    &text,
-                    TextAlign::Left,
-                    vec2(0.0, 0.0),
-                    Some(container_width),
-                    true,
-                    TextParams {
-                        font_size: 24,
-                        font: self.font.as_ref(),
-                        ..Default::default()
-                    },
-                );
-
-                let container_height = ((text_rect.h / 32.0) as i32) as f32 * 32.0 + 32.0 * 2.0;
-                let container_width = 416.0;
-                let topleft = vec2(
-                    (self.width - container_width) / 2.0,
-                    self.height - container_height - 30.0,
-                );
+                let container = self.draw_text_box(&text);
 
-                self.draw_container(
-                    Rect::new(topleft.x, topleft.y, container_width, container_height),
-                    "container1",
-                );
-
-                draw_text_aligned(
-                    &text,
-                    TextAlign::Left,
-                    topleft + vec2(0.0, 20.0),
-                    Some(container_width),
-                    false,
-                    TextParams {
-                        font_size: 32,
-                        font_scale: 0.75,
-                        font: self.font.as_ref(),
-                        ..Default::default()
-                    },
-                );
-
-                let right_arrow_pos =
-                    topleft + vec2(container_width - 32.0, container_height - 32.0);
+                let right_arrow_pos = vec2(container.right() - 32.0, container.bottom() - 32.0);
                 let right_arrow = Rect::new(right_arrow_pos.x, right_arrow_pos.y, 32.0, 32.0);
                 self.smap.get("right_arrow").draw(right_arrow_pos);
                 self.buttons.push(Button {
@@ -470,8 +774,14 @@ impl Ui {
                     container_height - margin.y * 2.0,
                 );
                 self.draw_container(in_container_rect, "container2");
+                let (_, plain, with_routes) = &self.levels[self.curr_level];
+                let preview = if settings().preview_patrols {
+                    with_routes
+                } else {
+                    plain
+                };
                 draw_texture_ex(
-                    &self.levels[self.curr_level].1.texture,
+                    &preview.texture,
                     in_topleft.x,
                     in_topleft.y,
                     WHITE,
@@ -480,6 +790,24 @@ impl Ui {
                         ..Default::default()
                     },
                 );
+                if save_data().is_cleared(&self.levels[self.curr_level].0) {
+                    draw_checkmark(in_topleft + vec2(16.0, 16.0), MY_GREEN);
+                }
+                let best_medal = save_data().best_medal(&self.levels[self.curr_level].0);
+                if let Some(medal) = best_medal {
+                    draw_text_aligned(
+                        &format!("Best: {}", medal.name()),
+                        TextAlign::Right,
+                        in_topleft + vec2(in_container_rect.w - 16.0, 32.0),
+                        None,
+                        false,
+                        TextParams {
+                            font_size: 32,
+                            font: self.font.as_ref(),
+                            ..Default::default()
+                        },
+                    );
+                }
 
                 let left_arrow_pos = in_topleft + vec2(0.0, in_container_rect.h - 32.0);
                 let left_arrow = Rect::new(left_arrow_pos.x, left_arrow_pos.y, 32.0, 32.0);
@@ -604,13 +932,170 @@ impl Ui {
                     },
                 );
 
+                draw_text_aligned(
+                    "TAB:  Switch Arrow",
+                    TextAlign::Left,
+                    topleft + vec2(xoff, 256.0),
+                    None,
+                    false,
+                    TextParams {
+                        font_size: 32,
+                        font: self.font.as_ref(),
+                        ..Default::default()
+                    },
+                );
+
+                self.draw_button(
+                    topleft + vec2(container_width / 2.0 - button_width / 2.0, 288.0),
+                    button_width,
+                    "Back",
+                    ButtonAction::GoHome,
+                );
+            }
+            UiState::Stats => {
+                draw_text_aligned(
+                    "Stats",
+                    TextAlign::Center,
+                    topleft + vec2(container_width / 2.0, 48.0),
+                    None,
+                    false,
+                    TextParams {
+                        font_size: 32,
+                        font: self.font.as_ref(),
+                        ..Default::default()
+                    },
+                );
+                let xoff = container_width / 5.0;
+
+                let lines = {
+                    let save = save_data();
+                    let seconds = save.playtime as u64;
+                    [
+                        format!(
+                            "Playtime: {}:{:02}:{:02}",
+                            seconds / 3600,
+                            seconds / 60 % 60,
+                            seconds % 60
+                        ),
+                        format!("Guards:   {}", save.kills),
+                        format!("Runs:     {}", save.runs),
+                        format!("Detected: {}", save.detections),
+                    ]
+                };
+                for (i, line) in lines.iter().enumerate() {
+                    draw_text_aligned(
+                        line,
+                        TextAlign::Left,
+                        topleft + vec2(xoff, 96.0 + i as f32 * 32.0),
+                        None,
+                        false,
+                        TextParams {
+                            font_size: 32,
+                            font: self.font.as_ref(),
+                            ..Default::default()
+                        },
+                    );
+                }
+
+                self.draw_button(
+                    topleft + vec2(container_width / 2.0 - button_width / 2.0, 288.0),
+                    button_width,
+                    "Back",
+                    ButtonAction::GoHome,
+                );
+            }
+            UiState::Options => {
+                draw_text_aligned(
+                    "Options",
+                    TextAlign::Center,
+                    topleft + vec2(container_width / 2.0, 48.0),
+                    None,
+                    false,
+                    TextParams {
+                        font_size: 32,
+                        font: self.font.as_ref(),
+                        ..Default::default()
+                    },
+                );
+
+                let scroll = self.options_scroll;
+                for (i, &setting) in OPTIONS
+                    .iter()
+                    .skip(scroll)
+                    .take(VISIBLE_OPTIONS)
+                    .enumerate()
+                {
+                    self.draw_button(
+                        topleft
+                            + vec2(
+                                container_width / 2.0 - button_width / 2.0,
+                                64.0 + i as f32 * 34.0,
+                            ),
+                        button_width,
+                        &setting.label(),
+                        ButtonAction::ChangeSetting(setting),
+                    );
+                }
+
+                // arrows beside the list when there are more rows above or below
+                let arrow_x = topleft.x + container_width / 2.0 + button_width / 2.0 + 48.0;
+                let arrow_color = Color::from_hex(0x3a3a50);
+                if scroll > 0 {
+                    let y = topleft.y + 64.0;
+                    draw_triangle(
+                        vec2(arrow_x, y),
+                        vec2(arrow_x - 8.0, y + 12.0),
+                        vec2(arrow_x + 8.0, y + 12.0),
+                        arrow_color,
+                    );
+                }
+                if scroll + VISIBLE_OPTIONS < OPTIONS.len() {
+                    let y = topleft.y + 64.0 + VISIBLE_OPTIONS as f32 * 34.0 - 2.0;
+                    draw_triangle(
+                        vec2(arrow_x, y),
+                        vec2(arrow_x - 8.0, y - 12.0),
+                        vec2(arrow_x + 8.0, y - 12.0),
+                        arrow_color,
+                    );
+                }
+
                 self.draw_button(
-                    topleft + vec2(container_width / 2.0 - button_width / 2.0, 256.0),
+                    topleft + vec2(container_width / 2.0 - button_width / 2.0, 340.0),
                     button_width,
                     "Back",
                     ButtonAction::GoHome,
                 );
             }
+            UiState::GameOver(level, time) => {
+                let (level, time) = (level.clone(), *time);
+                let minutes = (time / 60.0) as i32;
+                let seconds = time - minutes as f64 * 60.0;
+                for (text, y) in [
+                    ("Game Over".to_owned(), 48.0),
+                    (format!("Reached {level}"), 112.0),
+                    (format!("Time: {:02}:{:05.2}", minutes, seconds), 144.0),
+                ] {
+                    draw_text_aligned(
+                        &text,
+                        TextAlign::Center,
+                        topleft + vec2(container_width / 2.0, y),
+                        None,
+                        false,
+                        TextParams {
+                            font_size: 32,
+                            font: self.font.as_ref(),
+                            ..Default::default()
+                        },
+                    );
+                }
+
+                self.draw_button(
+                    topleft + vec2(container_width / 2.0 - button_width / 2.0, 288.0),
+                    button_width,
+                    "Main Menu",
+                    ButtonAction::Quit,
+                );
+            }
             UiState::MainMenu => {
                 draw_text_aligned(
                     if main_menu {
@@ -646,21 +1131,35 @@ impl Ui {
                 }
 
                 self.draw_button(
-                    topleft + vec2(container_width / 2.0 - button_width / 2.0, 160.0),
+                    topleft + vec2(container_width / 2.0 - button_width / 2.0, 144.0),
                     button_width,
                     "Select Level",
                     ButtonAction::GoToLevelSelect,
                 );
 
                 self.draw_button(
-                    topleft + vec2(container_width / 2.0 - button_width / 2.0, 224.0),
+                    topleft + vec2(container_width / 2.0 - button_width / 2.0, 192.0),
                     button_width,
                     "Controls",
                     ButtonAction::GoToControls,
                 );
 
                 self.draw_button(
-                    topleft + vec2(container_width / 2.0 - button_width / 2.0, 284.0),
+                    topleft + vec2(container_width / 2.0 - button_width / 2.0, 240.0),
+                    button_width,
+                    "Options",
+                    ButtonAction::GoToOptions,
+                );
+
+                self.draw_button(
+                    topleft + vec2(container_width / 2.0 - button_width / 2.0, 288.0),
+                    button_width,
+                    "Stats",
+                    ButtonAction::GoToStats,
+                );
+
+                self.draw_button(
+                    topleft + vec2(container_width / 2.0 - button_width / 2.0, 336.0),
                     button_width,
                     if !main_menu { "Main Menu" } else { "Quit" },
                     ButtonAction::Quit,
@@ -0,0 +1,26 @@
+// keeps the save in localStorage, load this after mq_js_bundle.js and sapp_jsutils.js
+miniquad_add_plugin({
+    name: "museum_assassin_storage",
+    version: 1,
+    register_plugin: function (importObject) {
+        // a missing save, or storage the browser won't let us use, reads as nil
+        importObject.env.museum_assassin_storage_get = function (key) {
+            key = consume_js_object(key);
+            try {
+                return js_object(window.localStorage.getItem(key));
+            } catch (e) {
+                console.warn("could not read " + key + ": " + e);
+                return js_object(null);
+            }
+        };
+        importObject.env.museum_assassin_storage_set = function (key, value) {
+            key = consume_js_object(key);
+            value = consume_js_object(value);
+            try {
+                window.localStorage.setItem(key, value);
+            } catch (e) {
+                console.warn("could not write " + key + ": " + e);
+            }
+        };
+    },
+});